@@ -0,0 +1,348 @@
+use std::io::{stdin, stdout, BufReader, Write};
+
+use gridloc::*;
+
+use crate::CliError;
+
+/// The number of steps which can be undone with `reverse-step`.
+const HISTORY_LIMIT: usize = 100_000;
+
+const HELP: &str = "\
+commands:
+  s, step [N]             run N steps (default 1)
+  c, continue             run until a breakpoint is hit or the program ends
+  rs, reverse-step [N]    undo N steps (default 1)
+  rw, reverse-write X Y   undo steps until just before cell (X, Y) was last written
+  b, break offset N       break before the source value at offset N
+  b, break line L [C]     break before the first value of line L (at or after column C)
+  b, break at X Y         break before the grid value at (X, Y) is evaluated
+  b, break read X Y       break after cell (X, Y) is read
+  b, break write X Y      break after cell (X, Y) is written
+  b, break instr C        break before the instruction written as C
+  b, break saved [SLOT]   break after a position is saved
+  d, delete ID            delete a breakpoint
+  i, info                 list breakpoints
+  p, print                print the current pointer
+  t, tapes                print the eval tape stack
+  pointers                print the pointer stack
+  saved                   print the saved positions
+  cell X Y                print a grid cell
+  set value N             set the pointer value
+  set pos X Y             set the pointer position
+  set dir D               set the pointer direction (< > ^ v)
+  set cell X Y N          set a grid cell, after which earlier steps can not be undone
+  q, quit                 stop debugging";
+
+/// Run an interactive debugging session on standard input, with the program
+/// running on `grid`.
+pub fn run<G: Grid>(builder: InterpreterBuilder, grid: G) -> Result<(), CliError> {
+    // the program reads its input one byte at a time, so that it never
+    // buffers debugger commands which follow its input
+    let interpreter = builder
+        .reader(Box::new(BufReader::with_capacity(1, stdin())))
+        .writer(Box::new(stdout()))
+        .record_history(HISTORY_LIMIT)
+        .build_on(grid)
+        .map_err(CliError::Build)?;
+    let mut debugger = Debugger::new(interpreter);
+
+    println!("type `help` for a list of commands");
+    print_location(&debugger);
+
+    loop {
+        print!("(gridloc) ");
+        stdout().flush().map_err(CliError::Io)?;
+
+        let mut line = String::new();
+        if stdin().read_line(&mut line).map_err(CliError::Io)? == 0 {
+            break;
+        }
+
+        let words: Vec<&str> = line.split_whitespace().collect();
+        if words.is_empty() {
+            continue;
+        }
+
+        if let "q" | "quit" = words[0] {
+            break;
+        }
+
+        match execute(&mut debugger, &words) {
+            Ok(()) => {}
+            Err(CommandError::Usage(message)) => println!("{}", message),
+            // keep the session open so that the failed state can be inspected
+            Err(CommandError::Runtime(error)) => println!("program stopped: {}", error),
+        }
+    }
+
+    debugger.interpreter_mut().state_mut().flush_tracer().map_err(CliError::from)
+}
+
+enum CommandError {
+    Usage(String),
+    Runtime(Error),
+}
+
+impl From<Error> for CommandError {
+    fn from(error: Error) -> Self {
+        CommandError::Runtime(error)
+    }
+}
+
+fn usage<T>(message: &str) -> Result<T, CommandError> {
+    Err(CommandError::Usage(message.to_string()))
+}
+
+fn execute<G: Grid>(debugger: &mut Debugger<G>, words: &[&str]) -> Result<(), CommandError> {
+    match words {
+        ["help"] | ["h"] => println!("{}", HELP),
+        ["s"] | ["step"] => {
+            let reason = debugger.step()?;
+            return report(debugger, reason);
+        }
+        ["s", count] | ["step", count] => {
+            for _ in 0..parse::<usize>(count)? {
+                let reason = debugger.step()?;
+
+                if reason != StopReason::Step {
+                    return report(debugger, reason);
+                }
+            }
+
+            return report(debugger, StopReason::Step);
+        }
+        ["c"] | ["continue"] => {
+            let reason = debugger.resume()?;
+            return report(debugger, reason);
+        }
+        ["rs"] | ["reverse-step"] => reverse_step(debugger, 1)?,
+        ["rs", count] | ["reverse-step", count] => reverse_step(debugger, parse(count)?)?,
+        ["rw", x, y] | ["reverse-write", x, y] => {
+            let position = parse_position(x, y)?;
+
+            match debugger.reverse_to_last_write(&position) {
+                Some(steps) => {
+                    println!("undid {} steps, {} is written next", steps, position);
+                    print_location(debugger);
+                }
+                None => return usage("no write to that cell is in the history"),
+            }
+        }
+        ["b", spec @ ..] | ["break", spec @ ..] => {
+            let breakpoint = parse_breakpoint(debugger, spec)?;
+            let id = debugger.add_breakpoint(breakpoint);
+            println!("breakpoint {}: {}", id, describe_breakpoint(debugger, &breakpoint));
+        }
+        ["d", id] | ["delete", id] => {
+            if debugger.remove_breakpoint(parse(id)?).is_none() {
+                return usage("no such breakpoint");
+            }
+        }
+        ["i"] | ["info"] => {
+            for (id, breakpoint) in debugger.breakpoints() {
+                println!("{:>3}: {}", id, describe_breakpoint(debugger, breakpoint));
+            }
+        }
+        ["p"] | ["print"] => {
+            if let Some(pointer) = debugger.interpreter().state().current_pointer() {
+                print_pointer(&pointer.borrow());
+            }
+        }
+        ["pointers"] => {
+            for (depth, pointer) in debugger.interpreter().state().pointers.iter().enumerate().rev() {
+                print!("{:>3}: ", depth);
+                print_pointer(&pointer.borrow());
+            }
+        }
+        ["t"] | ["tapes"] => {
+            for (depth, tape) in debugger.interpreter().state().eval_tapes.iter().enumerate().rev() {
+                println!("{:>3}: {} next {}", depth, describe_tape(&**tape), describe_value(tape.peek_next()));
+            }
+        }
+        ["saved"] => {
+            for (slot, position) in &debugger.interpreter().state().saved_positions {
+                println!("{:>3}: {}", slot, position);
+            }
+        }
+        ["cell", x, y] => {
+            let position = parse_position(x, y)?;
+            let value = debugger.interpreter().state().grid.borrow().get(&position);
+            println!("{} = {}", position, describe_value(Some(value)));
+        }
+        ["set", "cell", x, y, value] => {
+            let position = parse_position(x, y)?;
+            let value = parse(value)?;
+            if debugger.interpreter().state().grid.borrow_mut().try_set(&position, value).is_none() {
+                return usage(&format!("the grid can not grow to hold {}", position));
+            }
+
+            // the steps before never wrote this value, so they can not be
+            // undone without bringing back states the program never had
+            debugger.interpreter_mut().state_mut().clear_history();
+        }
+        ["set", field, args @ ..] => {
+            let pointer = match debugger.interpreter().state().current_pointer() {
+                Some(pointer) => pointer,
+                None => return usage("there is no pointer"),
+            };
+            let mut pointer = pointer.borrow_mut();
+
+            match (*field, args) {
+                ("value", [value]) => pointer.value = parse(value)?,
+                ("pos", [x, y]) => pointer.position = parse_position(x, y)?,
+                ("dir", [direction]) => pointer.direction = parse_direction(direction)?,
+                _ => return usage("usage: set value N | set pos X Y | set dir D | set cell X Y N"),
+            }
+        }
+        _ => return usage("unknown command, type `help` for a list of commands"),
+    }
+
+    Ok(())
+}
+
+fn reverse_step<G: Grid>(debugger: &mut Debugger<G>, count: usize) -> Result<(), CommandError> {
+    for undone in 0..count {
+        if !debugger.step_back() {
+            if undone == 0 {
+                return usage("there are no steps to undo");
+            }
+
+            println!("reached the start of the history");
+            break;
+        }
+    }
+
+    print_location(debugger);
+    Ok(())
+}
+
+fn report<G: Grid>(debugger: &Debugger<G>, reason: StopReason) -> Result<(), CommandError> {
+    match reason {
+        StopReason::Step => {}
+        StopReason::Breakpoint(id) => println!("hit breakpoint {}", id),
+        StopReason::Finished => {
+            println!("program finished after {} steps", debugger.steps());
+            return Ok(());
+        }
+    }
+
+    print_location(debugger);
+    Ok(())
+}
+
+fn print_location<G: Grid>(debugger: &Debugger<G>) {
+    let state = debugger.interpreter().state();
+
+    if let Some(tape) = state.current_tape() {
+        println!(
+            "step {}, depth {}, {}: next {}",
+            debugger.steps(),
+            state.eval_tapes.len() - 1,
+            describe_tape(tape),
+            describe_value(tape.peek_next()),
+        );
+    }
+}
+
+/// Where `tape` reads next, in the original source code if possible.
+fn describe_tape(tape: &dyn EvalTape) -> String {
+    match tape.source_position(tape.location()) {
+        Some(position) => format!("{} ({})", position, tape.location()),
+        None => tape.location().to_string(),
+    }
+}
+
+fn print_pointer(pointer: &Pointer) {
+    println!(
+        "{} facing {:?}, value {}, position stack {:?}",
+        pointer.position,
+        pointer.direction,
+        describe_value(Some(pointer.value)),
+        pointer.position_stack.iter().map(|p| (p.x, p.y)).collect::<Vec<_>>(),
+    );
+}
+
+fn describe_value(value: Option<u8>) -> String {
+    match value {
+        Some(value) if (b' '..=b'~').contains(&value) => format!("{} '{}'", value, value as char),
+        Some(value) => format!("{}", value),
+        None => "end of tape".to_string(),
+    }
+}
+
+fn describe_breakpoint<G: Grid>(debugger: &Debugger<G>, breakpoint: &Breakpoint) -> String {
+    match breakpoint {
+        Breakpoint::Tape(location) => {
+            match source_tape(debugger).and_then(|tape| tape.source_position(*location)) {
+                Some(position) => format!("before {} ({})", position, location),
+                None => format!("before {}", location),
+            }
+        }
+        Breakpoint::CellRead(position) => format!("after reading {}", position),
+        Breakpoint::CellWritten(position) => format!("after writing {}", position),
+        Breakpoint::Instruction(instruction) => format!("before any {:?}", instruction),
+        Breakpoint::PositionSaved(Some(slot)) => format!("after saving slot {}", slot),
+        Breakpoint::PositionSaved(None) => "after saving any slot".to_string(),
+    }
+}
+
+/// The tape over the source code, at the bottom of the stack.
+fn source_tape<'d, G: Grid>(debugger: &'d Debugger<G>) -> Option<&'d dyn EvalTape> {
+    debugger.interpreter().state().eval_tapes.first().map(|tape| &**tape)
+}
+
+fn parse_breakpoint<G: Grid>(debugger: &Debugger<G>, spec: &[&str]) -> Result<Breakpoint, CommandError> {
+    Ok(match spec {
+        ["offset", offset] => Breakpoint::Tape(TapeLocation::Source(parse(offset)?)),
+        ["line", line] | ["line", line, _] => {
+            let column = match spec {
+                [_, _, column] => parse(column)?,
+                _ => 1,
+            };
+            let position = SourcePosition { line: parse(line)?, column };
+
+            // find the offset which the source code at that position ended up at
+            let offset = source_tape(debugger)
+                .and_then(|tape| tape.source_map())
+                .and_then(|source_map| source_map.offset(position));
+
+            match offset {
+                Some(offset) => Breakpoint::Tape(TapeLocation::Source(offset)),
+                None => return usage("there is no code at that position"),
+            }
+        }
+        ["at", x, y] => Breakpoint::Tape(TapeLocation::Grid(parse_position(x, y)?)),
+        ["read", x, y] => Breakpoint::CellRead(parse_position(x, y)?),
+        ["write", x, y] => Breakpoint::CellWritten(parse_position(x, y)?),
+        ["instr", instruction] => {
+            let mut chars = instruction.chars();
+
+            match (chars.next().and_then(parse_instruction), chars.next()) {
+                (Some(instruction), None) => Breakpoint::Instruction(instruction),
+                _ => return usage("not an instruction"),
+            }
+        }
+        ["saved"] => Breakpoint::PositionSaved(None),
+        ["saved", slot] => Breakpoint::PositionSaved(Some(parse(slot)?)),
+        _ => return usage("usage: break offset N | line L [C] | at X Y | read X Y | write X Y | instr C | saved [SLOT]"),
+    })
+}
+
+fn parse<T: std::str::FromStr>(word: &str) -> Result<T, CommandError> {
+    word.parse()
+        .or_else(|_| usage(&format!("invalid number: {}", word)))
+}
+
+fn parse_position(x: &str, y: &str) -> Result<Position, CommandError> {
+    Ok(Position::new(parse(x)?, parse(y)?))
+}
+
+fn parse_direction(word: &str) -> Result<Direction, CommandError> {
+    Ok(match word {
+        ">" | "right" => Direction::Right,
+        "<" | "left" => Direction::Left,
+        "^" | "up" => Direction::Up,
+        "v" | "down" => Direction::Down,
+        _ => return usage("directions are < > ^ v"),
+    })
+}
@@ -0,0 +1,39 @@
+use std::fs;
+use std::io::{stdout, Write};
+use std::path::Path;
+
+use gridloc::*;
+
+use crate::{read_source, CliError};
+
+/// Print the program at `path` in its canonical layout, or with `write`,
+/// replace it. With `check`, only fail if it is not formatted already.
+pub fn run(path: &Path, write: bool, check: bool) -> Result<(), CliError> {
+    let source = read_source(Some(path))?;
+    let program = Program::parse(&source).map_err(CliError::Build)?;
+    let formatted = program.to_string();
+
+    // refuse to touch a program if formatting would change what it does
+    if significant_code(&formatted) != significant_code(&source) {
+        return Err(CliError::Format(path.to_owned()));
+    }
+
+    if check {
+        return if formatted == source {
+            Ok(())
+        } else {
+            Err(CliError::Unformatted(path.to_owned()))
+        };
+    }
+
+    if write {
+        if formatted != source {
+            fs::write(path, formatted)
+                .map_err(|error| CliError::Write { path: path.to_owned(), error })?;
+        }
+
+        return Ok(());
+    }
+
+    stdout().write_all(formatted.as_bytes()).map_err(CliError::Io)
+}
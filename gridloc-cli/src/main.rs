@@ -1,31 +1,499 @@
-use std::io::{stdin, stdout, BufReader};
-use std::path::PathBuf;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::io::{stdin, stdout, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
 
 use gridloc::*;
 
+use signal_hook::consts::SIGINT;
+
 use structopt::StructOpt;
 
+mod debug;
+mod format;
+
+mod visual;
+use visual::OutputBuffer;
+
+/// Exit code for a program which `fmt --check` found to be unformatted.
+const EXIT_UNFORMATTED: i32 = 1;
+/// Exit code for a program which `lint` warned about.
+const EXIT_WARNINGS: i32 = 1;
+/// Exit code for a program which `fmt` left alone, as formatting it would
+/// have changed what it does.
+const EXIT_FORMAT_REFUSED: i32 = 3;
+/// Exit code for an argument which is not valid (`EX_USAGE`).
+const EXIT_USAGE: i32 = 64;
+/// Exit code for a snapshot which could not be parsed or a program which is
+/// not valid (`EX_DATAERR`).
+const EXIT_DATA_ERROR: i32 = 65;
+/// Exit code for a source file which could not be read (`EX_NOINPUT`).
+const EXIT_NO_INPUT: i32 = 66;
+/// Exit code for a program which faulted while running (`EX_SOFTWARE`).
+const EXIT_RUNTIME_FAULT: i32 = 70;
+/// Exit code for a snapshot which could not be saved (`EX_CANTCREAT`).
+const EXIT_CANT_CREATE: i32 = 73;
+/// Exit code for a failure of the program's input or output (`EX_IOERR`).
+const EXIT_IO_ERROR: i32 = 74;
+/// Exit code for a program which exceeded a resource limit (`EX_TEMPFAIL`,
+/// as it might succeed with higher limits).
+const EXIT_LIMIT_EXCEEDED: i32 = 75;
+/// Exit code for a program which was stopped with ctrl-c.
+const EXIT_INTERRUPTED: i32 = 130;
+
 #[derive(StructOpt, Debug)]
 #[structopt(name = "gridloc")]
 struct Options {
-    /// Optional delay to be inserted between each instruction.
+    /// Optional delay (in seconds) to be inserted between each instruction.
     #[structopt(short, long)]
     delay: Option<f64>,
     /// Enable program visualizer
     #[structopt(long)]
     visual: bool,
-    /// File from which to read source code.
+    /// Grid to run the program on: `hash`, `dense`, or `torus:WxH` for a
+    /// fixed-size grid which wraps around (`torus` alone is 80x25). Resume a
+    /// snapshot on the same grid it was saved from.
+    #[structopt(long, default_value = "hash")]
+    grid: GridKind,
+    /// Seed for the random number generator, to make runs reproducible.
+    #[structopt(long)]
+    seed: Option<u64>,
+    /// Stop after this many steps, including those run before a snapshot.
+    #[structopt(long)]
+    max_steps: Option<u64>,
+    /// Stop once this many grid cells are non-zero.
+    #[structopt(long)]
+    max_cells: Option<usize>,
+    /// Stop once evaluation is nested deeper than this.
+    #[structopt(long)]
+    max_tape_depth: Option<usize>,
+    /// Stop once a pointer's position stack grows longer than this.
+    #[structopt(long)]
+    max_position_stack: Option<usize>,
+    /// Stop once the program has written more than this many bytes.
+    #[structopt(long)]
+    max_output: Option<usize>,
+    /// Save a snapshot of the program to this file when it stops, including
+    /// when it is interrupted with ctrl-c.
+    #[structopt(long, parse(from_os_str))]
+    save_on_exit: Option<PathBuf>,
+    /// Write a JSON Lines trace of every executed step to this file.
+    #[structopt(long, parse(from_os_str))]
+    trace: Option<PathBuf>,
+    /// Resume the program from a snapshot instead of reading FILE.
+    #[structopt(long, parse(from_os_str), conflicts_with = "FILE")]
+    resume: Option<PathBuf>,
+    /// File from which to read source code, or `-` for standard input.
     #[structopt(name = "FILE", parse(from_os_str))]
     file: Option<PathBuf>,
+    #[structopt(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(StructOpt, Debug)]
+enum Command {
+    /// Run a program in the interactive debugger.
+    Debug {
+        /// File from which to read source code.
+        #[structopt(name = "FILE", parse(from_os_str))]
+        file: PathBuf,
+    },
+    /// Print a program in the canonical layout.
+    Fmt {
+        /// Replace the file with the formatted program.
+        #[structopt(long)]
+        write: bool,
+        /// Only check whether the file is formatted already.
+        #[structopt(long, conflicts_with = "write")]
+        check: bool,
+        /// File from which to read source code.
+        #[structopt(name = "FILE", parse(from_os_str))]
+        file: PathBuf,
+    },
+    /// Compile a program into the source code of another language.
+    Compile {
+        /// Language to compile to: `c` or `rust`.
+        #[structopt(long, default_value = "c")]
+        target: Target,
+        /// File to write the compiled program to, instead of standard
+        /// output.
+        #[structopt(short, long, parse(from_os_str))]
+        output: Option<PathBuf>,
+        /// File from which to read source code, or `-` for standard input.
+        #[structopt(name = "FILE", parse(from_os_str))]
+        file: PathBuf,
+    },
+    /// Warn about likely mistakes in a program.
+    Lint {
+        /// File from which to read source code, or `-` for standard input.
+        #[structopt(name = "FILE", parse(from_os_str))]
+        file: PathBuf,
+    },
+}
+
+/// The kind of grid which a program runs on.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum GridKind {
+    Hash,
+    Dense,
+    Torus { width: usize, height: usize },
+}
+
+impl FromStr for GridKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "hash" => Ok(GridKind::Hash),
+            "dense" => Ok(GridKind::Dense),
+            "torus" => Ok(GridKind::Torus {
+                width: TorusGrid::DEFAULT_WIDTH,
+                height: TorusGrid::DEFAULT_HEIGHT,
+            }),
+            _ => match s.strip_prefix("torus:") {
+                Some(size) => parse_torus_size(size),
+                None => Err(format!("unknown grid `{}`", s)),
+            },
+        }
+    }
+}
+
+/// Parse the `WxH` size of a torus grid.
+fn parse_torus_size(size: &str) -> Result<GridKind, String> {
+    let invalid = || format!("torus size `{}` is not WIDTHxHEIGHT", size);
+
+    let (width, height) = size.split_once('x').ok_or_else(invalid)?;
+    let width: usize = width.parse().map_err(|_| invalid())?;
+    let height: usize = height.parse().map_err(|_| invalid())?;
+
+    if width == 0 || height == 0 || isize::try_from(width).is_err() || isize::try_from(height).is_err() {
+        return Err(format!("torus size `{}` is out of range", size));
+    }
+
+    Ok(GridKind::Torus { width, height })
+}
+
+#[derive(Debug)]
+enum CliError {
+    /// An argument is not valid.
+    Usage(String),
+    /// The source code could not be read.
+    Source { path: Option<PathBuf>, error: io::Error },
+    /// The source code is not a valid program.
+    Build(BuildError),
+    /// The program's input or output failed.
+    Io(io::Error),
+    /// The program faulted while running.
+    Runtime(Error),
+    /// The program was stopped with ctrl-c.
+    Interrupted,
+    /// The snapshot to resume from could not be read or resumed.
+    Resume { path: PathBuf, error: SnapshotError },
+    /// The snapshot of the stopped program could not be saved.
+    Save { path: PathBuf, error: SnapshotError },
+    /// The trace file could not be created.
+    Trace { path: PathBuf, error: io::Error },
+    /// Formatting would have changed what the program does.
+    Format(PathBuf),
+    /// The program is not formatted.
+    Unformatted(PathBuf),
+    /// The formatted program could not be written.
+    Write { path: PathBuf, error: io::Error },
+    /// The linter warned about the program this many times.
+    Warnings(usize),
+}
+
+impl CliError {
+    fn exit_code(&self) -> i32 {
+        match self {
+            CliError::Usage(_) => EXIT_USAGE,
+            CliError::Source { .. } => EXIT_NO_INPUT,
+            CliError::Build(_) => EXIT_DATA_ERROR,
+            CliError::Io(_) => EXIT_IO_ERROR,
+            CliError::Runtime(Error::ResourceLimit { .. }) => EXIT_LIMIT_EXCEEDED,
+            CliError::Runtime(_) => EXIT_RUNTIME_FAULT,
+            CliError::Interrupted => EXIT_INTERRUPTED,
+            CliError::Resume { error: SnapshotError::Io(_), .. } => EXIT_NO_INPUT,
+            CliError::Resume { .. } => EXIT_DATA_ERROR,
+            CliError::Save { .. } | CliError::Trace { .. } | CliError::Write { .. } => EXIT_CANT_CREATE,
+            CliError::Format(_) => EXIT_FORMAT_REFUSED,
+            CliError::Unformatted(_) => EXIT_UNFORMATTED,
+            CliError::Warnings(_) => EXIT_WARNINGS,
+        }
+    }
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CliError::Usage(message) => write!(f, "{}", message),
+            CliError::Source { path: Some(path), error } => {
+                write!(f, "could not read {}: {}", path.display(), error)
+            }
+            CliError::Source { path: None, error } => {
+                write!(f, "could not read source from standard input: {}", error)
+            }
+            CliError::Build(error) => write!(f, "invalid program: {}", error),
+            CliError::Io(error) => write!(f, "program I/O failed: {}", error),
+            CliError::Runtime(error) => write!(f, "runtime fault: {}", error),
+            CliError::Interrupted => write!(f, "interrupted"),
+            CliError::Resume { path, error } => {
+                write!(f, "could not resume from {}: {}", path.display(), error)
+            }
+            CliError::Save { path, error } => {
+                write!(f, "could not save snapshot to {}: {}", path.display(), error)
+            }
+            CliError::Trace { path, error } => {
+                write!(f, "could not create trace {}: {}", path.display(), error)
+            }
+            CliError::Format(path) => {
+                write!(f, "formatting {} would change what it does, so it was left alone", path.display())
+            }
+            CliError::Unformatted(path) => write!(f, "{} is not formatted", path.display()),
+            CliError::Write { path, error } => {
+                write!(f, "could not write {}: {}", path.display(), error)
+            }
+            CliError::Warnings(1) => write!(f, "1 warning"),
+            CliError::Warnings(count) => write!(f, "{} warnings", count),
+        }
+    }
+}
+
+impl From<Error> for CliError {
+    fn from(error: Error) -> Self {
+        match error {
+            Error::Io(error) => CliError::Io(error),
+            error => CliError::Runtime(error),
+        }
+    }
 }
 
 fn main() {
     let options = Options::from_args();
-    let source = "(\"Hello world 12345\")s";
 
-    let mut interpreter: Interpreter<HashGrid> = InterpreterBuilder::from_source(source)
+    if let Err(error) = run(&options) {
+        eprintln!("gridloc: {}", error);
+        process::exit(error.exit_code());
+    }
+}
+
+fn run(options: &Options) -> Result<(), CliError> {
+    match &options.command {
+        Some(Command::Debug { file }) => {
+            let source = read_source(Some(file))?;
+            let builder = configure(options, InterpreterBuilder::from_source(&source))?;
+
+            match options.grid {
+                GridKind::Hash => debug::run(builder, HashGrid::default()),
+                GridKind::Dense => debug::run(builder, DenseGrid::default()),
+                GridKind::Torus { width, height } => debug::run(builder, TorusGrid::new(width, height)),
+            }
+        }
+        Some(Command::Fmt { file, write, check }) => format::run(file, *write, *check),
+        Some(Command::Compile { target, output, file }) => compile_program(*target, output.as_deref(), file),
+        Some(Command::Lint { file }) => lint_program(file),
+        None => run_program(options),
+    }
+}
+
+fn run_program(options: &Options) -> Result<(), CliError> {
+    let source;
+    let builder = match &options.resume {
+        Some(path) => {
+            let snapshot = fs::File::open(path)
+                .map_err(SnapshotError::Io)
+                .and_then(Snapshot::read_from)
+                .map_err(|error| CliError::Resume { path: path.clone(), error })?;
+
+            InterpreterBuilder::from_snapshot(snapshot)
+        }
+        None => {
+            source = read_source(options.file.as_deref())?;
+            InterpreterBuilder::from_source(&source)
+        }
+    };
+
+    match options.grid {
+        GridKind::Hash => run_on(options, builder, HashGrid::default()),
+        GridKind::Dense => run_on(options, builder, DenseGrid::default()),
+        GridKind::Torus { width, height } => run_on(options, builder, TorusGrid::new(width, height)),
+    }
+}
+
+/// Run the program of `builder` on `grid`.
+fn run_on<G: Grid>(options: &Options, builder: InterpreterBuilder, grid: G) -> Result<(), CliError> {
+    let delay = options.delay
+        .map(|delay| Duration::try_from_secs_f64(delay).map_err(|_| {
+            CliError::Usage(format!("invalid delay `{}`: it must be a number of seconds which is not negative", delay))
+        }))
+        .transpose()?;
+
+    // only catch ctrl-c when there is a snapshot to save, and let a second
+    // ctrl-c stop the program regardless
+    let interrupted = Arc::new(AtomicBool::new(false));
+    if options.save_on_exit.is_some() {
+        signal_hook::flag::register_conditional_shutdown(SIGINT, EXIT_INTERRUPTED, interrupted.clone())
+            .and_then(|_| signal_hook::flag::register(SIGINT, interrupted.clone()))
+            .map_err(CliError::Io)?;
+    }
+
+    // the visualizer owns the terminal, so it shows the output itself
+    let output = OutputBuffer::default();
+    let writer: Box<dyn Write> = if options.visual {
+        Box::new(output.clone())
+    } else {
+        Box::new(stdout())
+    };
+
+    let mut interpreter = configure(options, builder)?
         .reader(Box::new(BufReader::new(stdin())))
-        .writer(Box::new(stdout()))
-        .build();
-    interpreter.run();
+        .writer(writer)
+        .build_on(grid)
+        .map_err(|error| match (error, &options.resume) {
+            (BuildError::Snapshot(error), Some(path)) => CliError::Resume { path: path.clone(), error },
+            (error, _) => CliError::Build(error),
+        })?;
+
+    let result = if options.visual {
+        visual::run(&mut interpreter, &output, delay.unwrap_or(visual::DEFAULT_DELAY), &interrupted)
+    } else {
+        run_plain(&mut interpreter, delay, &interrupted)
+    };
+
+    // stepping does not flush the trace, and a failure of the program
+    // takes precedence over failing to flush it
+    let flushed = interpreter.state_mut().flush_tracer();
+    let result = result.and(flushed);
+
+    if options.visual {
+        // leave the output on the terminal once the visualizer is closed
+        let mut stdout = stdout();
+        stdout.write_all(&output.contents())
+            .and_then(|_| stdout.flush())
+            .map_err(CliError::Io)?;
+    }
+
+    let result = match result {
+        Ok(()) if interrupted.load(Ordering::Relaxed) => Err(CliError::Interrupted),
+        result => result.map_err(CliError::from),
+    };
+
+    if let Some(path) = &options.save_on_exit {
+        let saved = fs::File::create(path)
+            .map_err(SnapshotError::Io)
+            .and_then(|file| interpreter.snapshot().write_to(io::BufWriter::new(file)))
+            .map_err(|error| CliError::Save { path: path.clone(), error });
+
+        // a failure of the program takes precedence
+        match (&result, saved) {
+            (Err(_), Err(error)) => eprintln!("gridloc: {}", error),
+            (Ok(()), Err(error)) => return Err(error),
+            (_, Ok(())) => {}
+        }
+    }
+
+    result
+}
+
+/// Compile the program at `path` for `target`, writing it to `output`.
+fn compile_program(target: Target, output: Option<&Path>, path: &Path) -> Result<(), CliError> {
+    let source = read_source(Some(path))?;
+    let compiled = target.compile(&source).map_err(CliError::Build)?;
+
+    match output {
+        Some(output) => fs::write(output, compiled)
+            .map_err(|error| CliError::Write { path: output.to_owned(), error }),
+        None => stdout().write_all(compiled.as_bytes()).map_err(CliError::Io),
+    }
+}
+
+/// Print a warning for every likely mistake in the program at `path`.
+fn lint_program(path: &Path) -> Result<(), CliError> {
+    let source = read_source(Some(path))?;
+    let lints = lint(&source);
+
+    for lint in &lints {
+        println!(
+            "{}:{}:{}: warning[{}]: {}",
+            path.display(),
+            lint.position.line,
+            lint.position.column,
+            lint.id,
+            lint.message,
+        );
+    }
+
+    if lints.is_empty() {
+        Ok(())
+    } else {
+        Err(CliError::Warnings(lints.len()))
+    }
+}
+
+/// Apply the seed, limits and tracing from the command line to `builder`.
+fn configure<'a>(options: &Options, builder: InterpreterBuilder<'a>) -> Result<InterpreterBuilder<'a>, CliError> {
+    let builder = builder
+        .limits(Limits {
+            max_steps: options.max_steps,
+            max_cells: options.max_cells,
+            max_tape_depth: options.max_tape_depth,
+            max_position_stack: options.max_position_stack,
+            max_output: options.max_output,
+        });
+
+    let builder = match options.seed {
+        Some(seed) => builder.seed(seed),
+        None => builder,
+    };
+
+    match &options.trace {
+        Some(path) => {
+            let file = fs::File::create(path)
+                .map_err(|error| CliError::Trace { path: path.clone(), error })?;
+
+            Ok(builder.tracer(Box::new(JsonLinesTracer::new(io::BufWriter::new(file)))))
+        }
+        None => Ok(builder),
+    }
+}
+
+fn run_plain<G: Grid>(interpreter: &mut Interpreter<G>, delay: Option<Duration>, interrupted: &AtomicBool) -> Result<(), Error> {
+    if delay.is_none() {
+        return interpreter.run_while(|| !interrupted.load(Ordering::Relaxed));
+    }
+
+    while !interrupted.load(Ordering::Relaxed) && interpreter.step()? {
+        if let Some(delay) = delay {
+            thread::sleep(delay);
+        }
+    }
+
+    Ok(())
+}
+
+/// Read the source code from `path`, or from standard input if there is no
+/// path or it is `-`.
+fn read_source(path: Option<&Path>) -> Result<String, CliError> {
+    match path {
+        Some(path) if path != Path::new("-") => {
+            fs::read_to_string(path).map_err(|error| CliError::Source {
+                path: Some(path.to_owned()),
+                error,
+            })
+        }
+        _ => {
+            let mut source = String::new();
+            stdin()
+                .read_to_string(&mut source)
+                .map_err(|error| CliError::Source { path: None, error })?;
+            Ok(source)
+        }
+    }
 }
@@ -0,0 +1,307 @@
+use std::cell::RefCell;
+use std::io;
+use std::io::{stdout, Stdout, Write};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::style::{Attribute, Color, Print, ResetColor, SetAttribute, SetBackgroundColor, SetForegroundColor};
+use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{execute, queue};
+
+use gridloc::*;
+
+/// Width of the information pane on the right of the screen.
+const SIDE_PANE_WIDTH: u16 = 36;
+
+/// Delay between frames when none was given on the command line.
+pub const DEFAULT_DELAY: Duration = Duration::from_millis(50);
+
+/// How long to wait for a key press while the program is not running.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Program output which is kept in memory so that it can be shown in the
+/// side pane.
+#[derive(Clone, Default)]
+pub struct OutputBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl OutputBuffer {
+    pub fn contents(&self) -> Vec<u8> {
+        self.0.borrow().clone()
+    }
+}
+
+impl Write for OutputBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Puts the terminal into the alternate screen for as long as it is alive,
+/// so that it is restored even if the interpreter panics.
+struct Screen {
+    out: Stdout,
+}
+
+impl Screen {
+    fn enter() -> io::Result<Self> {
+        let mut out = stdout();
+        execute!(out, EnterAlternateScreen, Hide)?;
+
+        Ok(Self { out })
+    }
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        let _ = terminal::disable_raw_mode();
+        let _ = execute!(self.out, ResetColor, Show, LeaveAlternateScreen);
+    }
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum Status {
+    Running,
+    Paused,
+    Finished,
+}
+
+/// Run the program until it finishes or the user quits, drawing a frame
+/// after every step.
+pub fn run<G: Grid>(interpreter: &mut Interpreter<G>, output: &OutputBuffer, delay: Duration, interrupted: &AtomicBool) -> Result<(), Error> {
+    let mut screen = Screen::enter()?;
+    let mut status = Status::Running;
+    let mut steps = 0;
+
+    while !interrupted.load(Ordering::Relaxed) {
+        draw(&mut screen.out, interpreter, &output.contents(), steps, status)?;
+
+        let timeout = match status {
+            Status::Running => delay,
+            // block until the user presses something
+            Status::Paused | Status::Finished => IDLE_TIMEOUT,
+        };
+
+        match wait_for_key(timeout)? {
+            Some(KeyCode::Char('q')) | Some(KeyCode::Esc) => break,
+            Some(KeyCode::Char(' ')) if status != Status::Finished => {
+                status = if status == Status::Paused { Status::Running } else { Status::Paused };
+                continue;
+            }
+            Some(KeyCode::Char('s')) if status == Status::Paused => {}
+            _ if status != Status::Running => continue,
+            _ => {}
+        }
+
+        if interpreter.step()? {
+            steps += 1;
+        } else {
+            status = Status::Finished;
+        }
+    }
+
+    Ok(())
+}
+
+/// Wait up to `timeout` for a key press. Raw mode is only enabled while
+/// waiting, so that the program can still read lines from the terminal.
+fn wait_for_key(timeout: Duration) -> io::Result<Option<KeyCode>> {
+    terminal::enable_raw_mode()?;
+
+    let result = (|| {
+        if event::poll(timeout)? {
+            if let Event::Key(KeyEvent { code, modifiers, kind: KeyEventKind::Press, .. }) = event::read()? {
+                if code == KeyCode::Char('c') && modifiers.contains(KeyModifiers::CONTROL) {
+                    return Ok(Some(KeyCode::Esc));
+                }
+
+                return Ok(Some(code));
+            }
+        }
+
+        Ok(None)
+    })();
+
+    terminal::disable_raw_mode()?;
+    result
+}
+
+fn draw<G: Grid, W: Write>(out: &mut W, interpreter: &Interpreter<G>, output: &[u8], steps: usize, status: Status) -> io::Result<()> {
+    let (columns, rows) = terminal::size()?;
+    let grid_width = columns.saturating_sub(SIDE_PANE_WIDTH + 1);
+    let grid_height = rows.saturating_sub(1);
+
+    queue!(out, Clear(ClearType::All))?;
+
+    draw_grid(out, interpreter, grid_width, grid_height)?;
+
+    // separator between the grid and the side pane
+    for row in 0..grid_height {
+        queue!(out, MoveTo(grid_width, row), Print('│'))?;
+    }
+
+    let side_lines = side_pane(interpreter, output, grid_height as usize);
+    for (row, line) in side_lines.iter().enumerate() {
+        queue!(out, MoveTo(grid_width + 1, row as u16))?;
+
+        if let SideLine::Heading(text) = line {
+            queue!(out, SetAttribute(Attribute::Bold), Print(text), SetAttribute(Attribute::Reset))?;
+        } else if let SideLine::Text(text) = line {
+            let text: String = text.chars().take(SIDE_PANE_WIDTH as usize).collect();
+            queue!(out, Print(text))?;
+        }
+    }
+
+    let status = match status {
+        Status::Running => "running  [space] pause  [q] quit",
+        Status::Paused => "paused  [space] resume  [s] step  [q] quit",
+        Status::Finished => "finished  [q] quit",
+    };
+    queue!(
+        out,
+        MoveTo(0, rows.saturating_sub(1)),
+        SetAttribute(Attribute::Reverse),
+        Print(format!(" step {} | {} ", steps, status)),
+        SetAttribute(Attribute::Reset),
+    )?;
+
+    out.flush()
+}
+
+/// Draw the region of the grid centered on the current pointer.
+fn draw_grid<G: Grid, W: Write>(out: &mut W, interpreter: &Interpreter<G>, width: u16, height: u16) -> io::Result<()> {
+    let state = interpreter.state();
+    let center = state.current_pointer()
+        .map(|p| p.borrow().position)
+        .unwrap_or_default();
+    let origin = Position::new(center.x - width as isize / 2, center.y + height as isize / 2);
+
+    let pointers: Vec<Pointer> = state.pointers.iter()
+        .map(|p| p.borrow().clone())
+        .collect();
+    let grid = state.grid.borrow();
+
+    for row in 0..height {
+        queue!(out, MoveTo(0, row))?;
+
+        for column in 0..width {
+            // rows grow downwards on the screen, but up is +y in the grid
+            let pos = Position::new(origin.x + column as isize, origin.y - row as isize);
+            let value = grid.get(&pos);
+
+            let pointer_index = pointers.iter().rposition(|p| p.position == pos);
+            let symbol = match pointer_index {
+                Some(index) if value == 0 => direction_arrow(pointers[index].direction),
+                _ => cell_symbol(value),
+            };
+
+            match pointer_index {
+                Some(index) => {
+                    let color = if index + 1 == pointers.len() { Color::Yellow } else { Color::DarkCyan };
+                    queue!(out, SetBackgroundColor(color), SetForegroundColor(Color::Black), Print(symbol), ResetColor)?;
+                }
+                None if !is_printable(value) && value != 0 => {
+                    queue!(out, SetForegroundColor(Color::Magenta), Print(symbol), ResetColor)?;
+                }
+                None => {
+                    queue!(out, Print(symbol))?;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+enum SideLine {
+    Heading(String),
+    Text(String),
+}
+
+/// Build the lines of the side pane: the tape stack, the pointers, the saved
+/// positions and as much of the output as still fits.
+fn side_pane<G: Grid>(interpreter: &Interpreter<G>, output: &[u8], height: usize) -> Vec<SideLine> {
+    let state = interpreter.state();
+    let mut lines = Vec::new();
+
+    lines.push(SideLine::Heading("tapes".to_string()));
+    for (depth, tape) in state.eval_tapes.iter().enumerate().rev() {
+        let next = tape.peek_next()
+            .map(|value| format!("'{}'", cell_symbol(value)))
+            .unwrap_or_else(|| "end".to_string());
+        lines.push(SideLine::Text(format!(" {} {} {}", depth, tape.location(), next)));
+    }
+
+    lines.push(SideLine::Heading("pointers".to_string()));
+    for (depth, pointer) in state.pointers.iter().enumerate().rev() {
+        let pointer = pointer.borrow();
+        lines.push(SideLine::Text(format!(
+            " {} {} {} {} '{}'",
+            depth,
+            direction_arrow(pointer.direction),
+            pointer.position,
+            pointer.value,
+            cell_symbol(pointer.value),
+        )));
+
+        for position in pointer.position_stack.iter().rev() {
+            lines.push(SideLine::Text(format!("    {}", position)));
+        }
+    }
+
+    if !state.saved_positions.is_empty() {
+        lines.push(SideLine::Heading("saved".to_string()));
+        for (slot, position) in &state.saved_positions {
+            lines.push(SideLine::Text(format!(" {:>3} {}", slot, position)));
+        }
+    }
+
+    lines.push(SideLine::Heading("output".to_string()));
+
+    // only show the tail of the output which fits in the remaining space
+    let remaining = height.saturating_sub(lines.len());
+    let output = String::from_utf8_lossy(output);
+    let mut output_lines: Vec<String> = Vec::new();
+    for line in output.split('\n') {
+        let chars: Vec<char> = line.chars().collect();
+        if chars.is_empty() {
+            output_lines.push(String::new());
+        }
+        for chunk in chars.chunks(SIDE_PANE_WIDTH as usize) {
+            output_lines.push(chunk.iter().collect());
+        }
+    }
+    let skip = output_lines.len().saturating_sub(remaining);
+    lines.extend(output_lines.into_iter().skip(skip).map(SideLine::Text));
+
+    lines.truncate(height);
+    lines
+}
+
+fn is_printable(value: u8) -> bool {
+    (b' '..=b'~').contains(&value)
+}
+
+fn cell_symbol(value: u8) -> char {
+    match value {
+        0 => ' ',
+        _ if is_printable(value) => value as char,
+        _ => '•',
+    }
+}
+
+fn direction_arrow(direction: Direction) -> char {
+    match direction {
+        Direction::Right => '→',
+        Direction::Left => '←',
+        Direction::Up => '↑',
+        Direction::Down => '↓',
+    }
+}
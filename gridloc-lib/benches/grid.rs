@@ -0,0 +1,90 @@
+//! Compares the grids on the kinds of access programs make.
+//!
+//! Run with `cargo bench -p gridloc-lib --bench grid`. To measure a change to
+//! a grid, save a baseline with `-- --save-baseline before` first and
+//! compare against it with `-- --baseline before`.
+
+use std::hint::black_box;
+use std::io;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use gridloc::*;
+
+/// Write a line of cells and read it back, as string literals and `s` do.
+fn line<G: Grid>(length: isize) -> u8 {
+    let mut grid = G::default();
+
+    for x in 0..length {
+        grid.try_set(&Position::new(x, 0), x as u8 | 1).unwrap();
+    }
+
+    (0..length).fold(0, |sum, x| sum ^ grid.get(&Position::new(x, 0)))
+}
+
+/// Read and write cells all over a small rectangle.
+fn scattered<G: Grid>(positions: &[Position]) -> u8 {
+    let mut grid = G::default();
+
+    positions.iter().fold(0, |sum, position| {
+        let value = grid.get(position);
+        grid.try_set(position, value.wrapping_add(1)).unwrap();
+        sum ^ value
+    })
+}
+
+/// Run a program which prints text from string literals.
+fn program<G: Grid>(source: &str) {
+    let mut interpreter = InterpreterBuilder::from_source(source)
+        .writer(Box::new(io::sink()))
+        .build::<G>()
+        .unwrap();
+
+    interpreter.run().unwrap();
+}
+
+fn bench_grids(c: &mut Criterion) {
+    let mut rng = StdRng::seed_from_u64(0);
+    let positions: Vec<_> = (0..10_000)
+        .map(|_| Position::new(rng.gen_range(0..64), rng.gen_range(0..64)))
+        .collect();
+    let source: String = (0..100)
+        .map(|index| format!("('the quick brown fox jumps over the lazy dog {}')sv.>", index))
+        .collect();
+    // long strings, which run through many chunks
+    let long_source = format!("('{}')sv.>", "gridloc ".repeat(512)).repeat(8);
+    // chunks whose coordinates differ only in their high bits, which a
+    // hasher that does not mix those into the low bits puts in one bucket
+    let colliding: Vec<_> = (0..2048)
+        .map(|index| Position::new(0, index << 40))
+        .collect();
+
+    let mut group = c.benchmark_group("line");
+    group.bench_function("hash", |b| b.iter(|| line::<HashGrid>(black_box(4096))));
+    group.bench_function("dense", |b| b.iter(|| line::<DenseGrid>(black_box(4096))));
+    group.finish();
+
+    let mut group = c.benchmark_group("scattered");
+    group.bench_function("hash", |b| b.iter(|| scattered::<HashGrid>(black_box(&positions))));
+    group.bench_function("dense", |b| b.iter(|| scattered::<DenseGrid>(black_box(&positions))));
+    group.finish();
+
+    let mut group = c.benchmark_group("program");
+    group.bench_function("hash", |b| b.iter(|| program::<HashGrid>(black_box(&source))));
+    group.bench_function("dense", |b| b.iter(|| program::<DenseGrid>(black_box(&source))));
+    group.finish();
+
+    let mut group = c.benchmark_group("long_strings");
+    group.bench_function("hash", |b| b.iter(|| program::<HashGrid>(black_box(&long_source))));
+    group.bench_function("dense", |b| b.iter(|| program::<DenseGrid>(black_box(&long_source))));
+    group.finish();
+
+    // a dense grid can not hold cells this far apart
+    let mut group = c.benchmark_group("colliding");
+    group.bench_function("hash", |b| b.iter(|| scattered::<HashGrid>(black_box(&colliding))));
+    group.finish();
+}
+
+criterion_group!(benches, bench_grids);
+criterion_main!(benches);
@@ -0,0 +1,40 @@
+//! Compares running a program all at once, through its compiled source
+//! code, with stepping through it.
+//!
+//! Run with `cargo bench -p gridloc-lib --bench run`.
+
+use std::hint::black_box;
+use std::io;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+
+use gridloc::*;
+
+fn build(source: &str) -> Interpreter<'static, HashGrid> {
+    InterpreterBuilder::from_source(source)
+        .writer(Box::new(io::sink()))
+        .build()
+        .unwrap()
+}
+
+fn run(source: &str) {
+    build(source).run().unwrap();
+}
+
+fn step(source: &str) {
+    let mut interpreter = build(source);
+    while interpreter.step().unwrap() {}
+}
+
+fn bench_loops(c: &mut Criterion) {
+    // nested loops counting down, which mostly jump back and forth
+    let source = format!("01,.{}", "10,[<.>FF[(..)(^.>)-]._<.>-.,]".repeat(4));
+
+    let mut group = c.benchmark_group("loops");
+    group.bench_function("run", |b| b.iter(|| run(black_box(&source))));
+    group.bench_function("step", |b| b.iter(|| step(black_box(&source))));
+    group.finish();
+}
+
+criterion_group!(benches, bench_loops);
+criterion_main!(benches);
@@ -0,0 +1,153 @@
+use std::fmt::Write;
+
+use crate::*;
+
+use super::{merge_steps, Step};
+
+/// The grid, pointers and instructions which compiled programs are built
+/// on, along with an interpreter for code which is evaluated with `e`.
+const RUNTIME: &str = include_str!("runtime.c");
+
+/// The most bytes of a literal to write on one line.
+const LITERAL_LINE_LENGTH: usize = 48;
+
+/// Compile `program` into a standalone C program, which reads from standard
+/// input and writes to standard output like [`Interpreter::run`].
+///
+/// The source code is compiled to C statements, while code which is
+/// evaluated from the grid is run by an interpreter built into the program.
+/// `?` uses a different generator, so random programs do not match.
+pub fn compile_c(program: &Program) -> String {
+    let mut out = String::new();
+
+    out.push_str("/* compiled from gridloc source code */\n\n");
+    out.push_str(RUNTIME);
+    out.push_str("\nstatic void run(struct machine *m) {\n");
+    out.push_str("    struct pointer *p = m->pointers;\n");
+    block(&mut out, &merge_steps(&program.items), 1);
+    out.push_str("}\n");
+
+    out.push_str(concat!(
+        "\n",
+        "int main(void) {\n",
+        "    static struct machine m;\n",
+        "\n",
+        "    m.rng = (uint64_t)time(NULL) * 0x9e3779b97f4a7c15u | 1;\n",
+        "    push_pointer(&m);\n",
+        "    run(&m);\n",
+        "\n",
+        "    fflush(stdout);\n",
+        "    return 0;\n",
+        "}\n",
+    ));
+
+    out
+}
+
+fn block(out: &mut String, steps: &[Step], depth: usize) {
+    let indent = "    ".repeat(depth);
+
+    for step in steps {
+        match step {
+            Step::Instruction(instruction) => {
+                for line in statement(*instruction) {
+                    writeln!(out, "{}{}", indent, line).unwrap();
+                }
+            }
+            Step::Move(count) => writeln!(out, "{}move_by(p, {});", indent, count).unwrap(),
+            Step::SetValue(value) => writeln!(out, "{}p->value = {};", indent, value).unwrap(),
+            Step::Loop(body) => {
+                writeln!(out, "{}while (p->value != 0) {{", indent).unwrap();
+                block(out, body, depth + 1);
+                writeln!(out, "{}}}", indent).unwrap();
+            }
+            Step::Literal([]) => {}
+            Step::Literal(bytes) => {
+                write!(out, "{}literal(m, p,", indent).unwrap();
+
+                for chunk in bytes.chunks(LITERAL_LINE_LENGTH) {
+                    write!(out, "\n{}    \"{}\"", indent, escape_c(chunk)).unwrap();
+                }
+
+                writeln!(out, ", {});", bytes.len()).unwrap();
+            }
+        }
+    }
+}
+
+/// The C statements which run `instruction` with the source code's pointer.
+fn statement(instruction: Instruction) -> Vec<String> {
+    use Instruction::*;
+
+    let call = |function: &str, arguments: &str| vec![format!("{}({});", function, arguments)];
+
+    match instruction {
+        SetDirection(direction) => {
+            let direction = match direction {
+                Direction::Right => "RIGHT",
+                Direction::Left => "LEFT",
+                Direction::Up => "UP",
+                Direction::Down => "DOWN",
+            };
+
+            call("op_set_direction", &format!("p, {}", direction))
+        }
+        MoveOne => call("move_by", "p, 1"),
+        MoveMultiple => call("op_move_multiple", "p"),
+        ReadValue => call("op_read_value", "m, p"),
+        WriteValue => call("op_write_value", "m, p"),
+        WriteValueMove => call("op_write_value_move", "m, p"),
+        SwapValue => call("op_swap_value", "m, p"),
+        PushPosition => call("op_push_position", "p"),
+        PopPosition => call("op_pop_position", "p"),
+        SwapPosition => call("op_swap_position", "p"),
+        SavePosition => call("op_save_position", "m, p"),
+        LoadPosition => call("op_load_position", "m, p"),
+        Evaluate => vec![
+            // the code ends the program if it is not killed first
+            "if (!evaluate(m)) {".to_string(),
+            "    return;".to_string(),
+            "}".to_string(),
+            // which may have moved the pointers
+            "p = m->pointers;".to_string(),
+        ],
+        Kill => vec!["return;".to_string()],
+        Value(digit) => call("op_value", &format!("p, {}", digit)),
+        Add => call("op_add", "m, p"),
+        Subtract => call("op_subtract", "m, p"),
+        Multiply => call("op_multiply", "m, p"),
+        Divide => call("op_divide", "m, p"),
+        Modulo => call("op_modulo", "m, p"),
+        Equals => call("op_equals", "m, p"),
+        GreaterThan => call("op_greater_than", "m, p"),
+        LogicalAnd => call("op_logical_and", "m, p"),
+        LogicalOr => call("op_logical_or", "m, p"),
+        LogicalNot => call("op_logical_not", "p"),
+        Random => call("op_random", "m, p"),
+        Write { kind: IOKind::Character } => call("op_write_character", "m, p"),
+        Write { kind: IOKind::Number } => call("op_write_number", "m, p"),
+        Write { kind: IOKind::String } => call("op_write_string", "m, p"),
+        Read { kind: IOKind::Character } => call("op_read", "m, p, READ_CHARACTER"),
+        Read { kind: IOKind::Number } => call("op_read", "m, p, READ_NUMBER"),
+        Read { kind: IOKind::String } => call("op_read", "m, p, READ_STRING"),
+        // loops and literals are steps of their own
+        StartLoop | EndLoop | ToggleStringMode { .. } => unreachable!(),
+    }
+}
+
+/// Escape `bytes` for a C string literal. Octal escapes are used for
+/// anything but plain characters, as they never run into the next one.
+fn escape_c(bytes: &[u8]) -> String {
+    let mut escaped = String::with_capacity(bytes.len());
+
+    for &byte in bytes {
+        match byte {
+            // `?` could start a trigraph
+            b'"' | b'\\' | b'?' => write!(escaped, "\\{}", byte as char).unwrap(),
+            b' '..=b'~' => escaped.push(byte as char),
+            _ => write!(escaped, "\\{:03o}", byte).unwrap(),
+        }
+    }
+
+    escaped
+}
@@ -0,0 +1,118 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::*;
+
+mod c;
+pub use c::*;
+
+mod native;
+pub use native::*;
+
+mod rust;
+pub use rust::*;
+
+/// A language which programs can be compiled to.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Target {
+    /// A standalone C program.
+    C,
+    /// A Rust module which depends on this crate.
+    Rust,
+}
+
+impl Target {
+    pub const ALL: [Target; 2] = [Target::C, Target::Rust];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Target::C => "c",
+            Target::Rust => "rust",
+        }
+    }
+
+    /// Compile `source` into a program of this language.
+    pub fn compile(&self, source: &str) -> Result<String, BuildError> {
+        let program = Program::parse(source)?;
+
+        match self {
+            Target::C => Ok(compile_c(&program)),
+            Target::Rust => Ok(compile_rust(&program)),
+        }
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Target {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Target::ALL.iter()
+            .copied()
+            .find(|target| target.name() == s)
+            .ok_or_else(|| format!("unknown target `{}`", s))
+    }
+}
+
+/// A straight run of the program, with consecutive digits and moves merged
+/// into one step.
+#[derive(Clone, Debug, PartialEq)]
+enum Step<'a> {
+    Instruction(Instruction),
+    /// Move the pointer this many cells.
+    Move(usize),
+    /// Set the pointer value, as two or more digits in a row do.
+    SetValue(u8),
+    Loop(Vec<Step<'a>>),
+    /// Write these bytes to the grid, moving after each one.
+    Literal(&'a [u8]),
+}
+
+/// Merge the items of a program into steps, dropping everything which does
+/// nothing.
+fn merge_steps(items: &[Item]) -> Vec<Step<'_>> {
+    use Instruction::*;
+
+    let mut steps = Vec::new();
+    let mut digits = Vec::new();
+
+    let flush_digits = |digits: &mut Vec<u8>, steps: &mut Vec<Step>| {
+        match digits.as_slice() {
+            [] => {}
+            [digit] => steps.push(Step::Instruction(Value(*digit))),
+            [.., high, low] => steps.push(Step::SetValue(high << 4 | low)),
+        }
+        digits.clear();
+    };
+
+    for item in items {
+        let step = match item {
+            Item::Instruction(Value(digit)) => {
+                digits.push(*digit);
+                continue;
+            }
+            Item::Comment(_) | Item::Other(_) | Item::LineBreak => continue,
+            Item::Instruction(MoveOne) => match steps.last_mut() {
+                Some(Step::Move(count)) if digits.is_empty() => {
+                    *count += 1;
+                    continue;
+                }
+                _ => Step::Move(1),
+            },
+            Item::Instruction(instruction) => Step::Instruction(*instruction),
+            Item::Loop(body) => Step::Loop(merge_steps(body)),
+            Item::Literal { bytes, .. } => Step::Literal(bytes),
+        };
+
+        flush_digits(&mut digits, &mut steps);
+        steps.push(step);
+    }
+
+    flush_digits(&mut digits, &mut steps);
+    steps
+}
@@ -0,0 +1,147 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::io::{BufRead, Write};
+use std::mem;
+use std::rc::Rc;
+
+use rand::{rngs::StdRng, thread_rng, RngCore, SeedableRng};
+
+use crate::*;
+
+/// The state of a program which was compiled to Rust by [`compile_rust`].
+///
+/// The compiled code runs the instructions of the source code on it
+/// directly. Only code which is evaluated from the grid with `e` is handed to
+/// a [`ProgramState`], which steps through it as usual.
+pub struct NativeState<G: Grid> {
+    pub grid: G,
+    /// The pointer of the source code.
+    pub pointer: Pointer,
+    pub saved_positions: BTreeMap<u8, Position>,
+    /// Stop the program once it exceeds any of these. Steps are only
+    /// counted in code evaluated with `e`.
+    pub limits: Limits,
+    output_bytes: usize,
+    rng: Box<dyn RngCore>,
+}
+
+impl<G: Grid> NativeState<G> {
+    pub fn new() -> Self {
+        Self::with_rng(Box::new(thread_rng()))
+    }
+
+    /// Seed the source of randomness for `?`, so that runs are reproducible.
+    pub fn with_seed(seed: u64) -> Self {
+        Self::with_rng(Box::new(StdRng::seed_from_u64(seed)))
+    }
+
+    pub fn with_rng(rng: Box<dyn RngCore>) -> Self {
+        Self {
+            grid: G::default(),
+            pointer: Pointer::default(),
+            saved_positions: BTreeMap::new(),
+            limits: Limits::default(),
+            output_bytes: 0,
+            rng,
+        }
+    }
+
+    /// Move the pointer `amount` cells, as that many `.` instructions do.
+    pub fn move_by(&mut self, amount: isize) {
+        self.pointer.position = self.pointer.position + self.pointer.direction.unit_vector() * amount;
+        self.wrap_pointer();
+    }
+
+    /// Write a string literal to the grid, moving after each value.
+    pub fn literal(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        let mut pointer = mem::take(&mut self.pointer);
+        let result = bytes.iter().try_for_each(|&value| {
+            self.context(pointer.position).write_cell(&pointer.position, value)?;
+            pointer.move_pointer(1);
+            Ok(())
+        });
+
+        self.pointer = pointer;
+        self.wrap_pointer();
+        result
+    }
+
+    /// Bring the pointer back onto a grid which wraps around, as
+    /// [`ProgramState`] does after every step.
+    fn wrap_pointer(&mut self) {
+        self.pointer.position = self.grid.wrap(self.pointer.position);
+    }
+
+    /// Run `instruction` exactly as a [`ProgramState`] would. Loop brackets,
+    /// quotes, `e` and `@` fail with [`Error::Unsupported`], as the compiled
+    /// code runs those itself.
+    pub fn execute<R: BufRead, W: Write>(&mut self, instruction: Instruction, reader: &mut R, writer: &mut W) -> Result<(), Error> {
+        let mut pointer = mem::take(&mut self.pointer);
+        let result = self.context(pointer.position).execute(instruction, &mut pointer, reader, writer);
+
+        self.pointer = pointer;
+        self.wrap_pointer();
+        result
+    }
+
+    /// What the instructions of the pointer, which is at `position`, act
+    /// on. Nothing watches the events of compiled code, or undoes it.
+    fn context(&mut self, position: Position) -> PointerContext<'_, G, dyn RngCore> {
+        PointerContext {
+            // compiled code has no tape to point into
+            location: Location {
+                depth: 0,
+                tape: TapeLocation::Source(0),
+                pointer: Some(position),
+                source: None,
+            },
+            grid: &mut self.grid,
+            saved_positions: &mut self.saved_positions,
+            rng: &mut *self.rng,
+            limits: &self.limits,
+            output_bytes: &mut self.output_bytes,
+            events: None,
+            consumed_input: None,
+        }
+    }
+
+    /// Evaluate the grid code under the pointer, until it is killed with `@`.
+    /// Returns `false` if the code ended first, which ends the program.
+    pub fn evaluate<R: BufRead, W: Write>(&mut self, reader: &mut R, writer: &mut W) -> Result<bool, Error> {
+        let mut state = ProgramState::new(Box::new(SourceTape::from("")), &mut *self.rng);
+        state.eval_tapes.clear();
+        state.limits = self.limits;
+        state.output_bytes = self.output_bytes;
+        state.grid = Rc::new(RefCell::new(mem::take(&mut self.grid)));
+        state.pointers = vec![Rc::new(RefCell::new(mem::take(&mut self.pointer)))];
+        state.saved_positions = mem::take(&mut self.saved_positions);
+
+        let result = state.evaluate(reader, writer);
+
+        // the tapes and the other pointers share the grid and our pointer,
+        // so drop them before taking those back
+        state.eval_tapes.clear();
+        state.pointers.truncate(1);
+
+        let pointer = state.pointers.pop().expect("the source code's pointer is never killed by evaluated code");
+        self.pointer = unwrap_shared(pointer);
+        self.grid = unwrap_shared(mem::take(&mut state.grid));
+        self.saved_positions = mem::take(&mut state.saved_positions);
+        self.output_bytes = state.output_bytes;
+
+        result
+    }
+}
+
+impl<G: Grid> Default for NativeState<G> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn unwrap_shared<T>(shared: Rc<RefCell<T>>) -> T {
+    match Rc::try_unwrap(shared) {
+        Ok(value) => value.into_inner(),
+        Err(_) => panic!("state of evaluated code is still shared"),
+    }
+}
@@ -0,0 +1,631 @@
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <time.h>
+
+#define CHUNK_SHIFT 3
+#define CHUNK_LENGTH (1 << CHUNK_SHIFT)
+#define CHUNK_MASK (CHUNK_LENGTH - 1)
+
+#define EXIT_RUNTIME_FAULT 70
+#define EXIT_IO_ERROR 74
+
+enum direction { RIGHT, LEFT, UP, DOWN };
+
+static const int64_t DIRECTION_X[] = { 1, -1, 0, 0 };
+static const int64_t DIRECTION_Y[] = { 0, 0, 1, -1 };
+
+struct position {
+    int64_t x, y;
+};
+
+struct chunk {
+    int64_t x, y;
+    uint8_t cells[CHUNK_LENGTH * CHUNK_LENGTH];
+};
+
+/* the grid is split into square chunks, which are kept in an open
+   addressing hash table and only allocated once they are written to */
+struct grid {
+    struct chunk **slots;
+    size_t capacity, count;
+    struct chunk *last;
+};
+
+struct pointer {
+    struct position position;
+    enum direction direction;
+    uint8_t value;
+    struct position *stack;
+    size_t stack_len, stack_capacity;
+};
+
+struct machine {
+    struct grid grid;
+    /* the pointer of the source code comes first, followed by the pointer
+       of every `e` which is being evaluated */
+    struct pointer *pointers;
+    size_t pointer_count, pointer_capacity;
+    struct position saved[256];
+    uint8_t is_saved[256];
+    /* the quote of the literal which the grid code is in, if any */
+    uint8_t string_mode;
+    uint64_t rng;
+};
+
+static void fault(int code, const char *message) {
+    fflush(stdout);
+    fprintf(stderr, "gridloc: %s\n", message);
+    exit(code);
+}
+
+static void *allocate(void *memory, size_t size) {
+    memory = realloc(memory, size);
+    if (memory == NULL) {
+        fault(EXIT_RUNTIME_FAULT, "out of memory");
+    }
+    return memory;
+}
+
+static int64_t wrapping_add(int64_t a, int64_t b) {
+    return (int64_t)((uint64_t)a + (uint64_t)b);
+}
+
+static size_t chunk_hash(int64_t x, int64_t y) {
+    uint64_t hash = (uint64_t)x * 0x9e3779b97f4a7c15u ^ (uint64_t)y * 0xc2b2ae3d27d4eb4fu;
+    return (size_t)(hash ^ hash >> 29);
+}
+
+static struct chunk *grid_find(struct grid *grid, int64_t x, int64_t y) {
+    size_t index;
+
+    if (grid->last != NULL && grid->last->x == x && grid->last->y == y) {
+        return grid->last;
+    }
+    if (grid->capacity == 0) {
+        return NULL;
+    }
+
+    index = chunk_hash(x, y) & (grid->capacity - 1);
+    while (grid->slots[index] != NULL) {
+        struct chunk *chunk = grid->slots[index];
+        if (chunk->x == x && chunk->y == y) {
+            grid->last = chunk;
+            return chunk;
+        }
+        index = (index + 1) & (grid->capacity - 1);
+    }
+    return NULL;
+}
+
+static void grid_insert(struct grid *grid, struct chunk *chunk) {
+    size_t index = chunk_hash(chunk->x, chunk->y) & (grid->capacity - 1);
+    while (grid->slots[index] != NULL) {
+        index = (index + 1) & (grid->capacity - 1);
+    }
+    grid->slots[index] = chunk;
+}
+
+static struct chunk *grid_chunk(struct grid *grid, int64_t x, int64_t y) {
+    struct chunk *chunk = grid_find(grid, x, y);
+    if (chunk != NULL) {
+        return chunk;
+    }
+
+    /* keep the table at most half full */
+    if ((grid->count + 1) * 2 > grid->capacity) {
+        struct chunk **old = grid->slots;
+        size_t old_capacity = grid->capacity, i;
+
+        grid->capacity = old_capacity == 0 ? 64 : old_capacity * 2;
+        grid->slots = calloc(grid->capacity, sizeof(struct chunk *));
+        if (grid->slots == NULL) {
+            fault(EXIT_RUNTIME_FAULT, "out of memory");
+        }
+        for (i = 0; i < old_capacity; i++) {
+            if (old[i] != NULL) {
+                grid_insert(grid, old[i]);
+            }
+        }
+        free(old);
+    }
+
+    chunk = calloc(1, sizeof(struct chunk));
+    if (chunk == NULL) {
+        fault(EXIT_RUNTIME_FAULT, "out of memory");
+    }
+    chunk->x = x;
+    chunk->y = y;
+    grid_insert(grid, chunk);
+    grid->count++;
+    grid->last = chunk;
+    return chunk;
+}
+
+/* shifting rounds towards negative infinity, so negative positions belong
+   to the chunk below them */
+static uint8_t grid_get(struct grid *grid, struct position position) {
+    struct chunk *chunk = grid_find(grid, position.x >> CHUNK_SHIFT, position.y >> CHUNK_SHIFT);
+    if (chunk == NULL) {
+        return 0;
+    }
+    return chunk->cells[(position.y & CHUNK_MASK) * CHUNK_LENGTH + (position.x & CHUNK_MASK)];
+}
+
+static void grid_set(struct grid *grid, struct position position, uint8_t value) {
+    struct chunk *chunk;
+
+    /* there is no need to allocate a chunk to write a zero */
+    if (value == 0 && grid_find(grid, position.x >> CHUNK_SHIFT, position.y >> CHUNK_SHIFT) == NULL) {
+        return;
+    }
+
+    chunk = grid_chunk(grid, position.x >> CHUNK_SHIFT, position.y >> CHUNK_SHIFT);
+    chunk->cells[(position.y & CHUNK_MASK) * CHUNK_LENGTH + (position.x & CHUNK_MASK)] = value;
+}
+
+static void move_by(struct pointer *p, int64_t amount) {
+    p->position.x = wrapping_add(p->position.x, DIRECTION_X[p->direction] * amount);
+    p->position.y = wrapping_add(p->position.y, DIRECTION_Y[p->direction] * amount);
+}
+
+static uint8_t cell(struct machine *m, struct pointer *p) {
+    return grid_get(&m->grid, p->position);
+}
+
+/* write `value` under the pointer and move past it */
+static void write_move(struct machine *m, struct pointer *p, uint8_t value) {
+    grid_set(&m->grid, p->position, value);
+    move_by(p, 1);
+}
+
+static void literal(struct machine *m, struct pointer *p, const char *bytes, size_t length) {
+    size_t i;
+    for (i = 0; i < length; i++) {
+        write_move(m, p, (uint8_t)bytes[i]);
+    }
+}
+
+/* write a byte as the character with the same code point */
+static void put_character(uint8_t value) {
+    if (value < 0x80) {
+        putchar(value);
+    } else {
+        putchar(0xc0 | value >> 6);
+        putchar(0x80 | (value & 0x3f));
+    }
+}
+
+static int is_whitespace(uint32_t c) {
+    return (c >= 0x09 && c <= 0x0d) || c == 0x20 || c == 0x85 || c == 0xa0 || c == 0x1680
+        || (c >= 0x2000 && c <= 0x200a) || c == 0x2028 || c == 0x2029 || c == 0x202f
+        || c == 0x205f || c == 0x3000;
+}
+
+/* decode the UTF-8 in `bytes`, returning the number of characters or -1 if
+   it is not valid */
+static long decode_utf8(const uint8_t *bytes, size_t length, uint32_t *characters) {
+    size_t i = 0;
+    long count = 0;
+
+    while (i < length) {
+        uint8_t lead = bytes[i];
+        uint32_t c, min;
+        size_t extra, j;
+
+        if (lead < 0x80) {
+            c = lead, extra = 0, min = 0;
+        } else if ((lead & 0xe0) == 0xc0) {
+            c = lead & 0x1f, extra = 1, min = 0x80;
+        } else if ((lead & 0xf0) == 0xe0) {
+            c = lead & 0x0f, extra = 2, min = 0x800;
+        } else if ((lead & 0xf8) == 0xf0) {
+            c = lead & 0x07, extra = 3, min = 0x10000;
+        } else {
+            return -1;
+        }
+
+        if (extra > length - i - 1) {
+            return -1;
+        }
+        for (j = 1; j <= extra; j++) {
+            if ((bytes[i + j] & 0xc0) != 0x80) {
+                return -1;
+            }
+            c = c << 6 | (bytes[i + j] & 0x3f);
+        }
+        if (c < min || c > 0x10ffff || (c >= 0xd800 && c <= 0xdfff)) {
+            return -1;
+        }
+
+        characters[count++] = c;
+        i += extra + 1;
+    }
+
+    return count;
+}
+
+static void op_set_direction(struct pointer *p, enum direction direction) {
+    p->direction = direction;
+}
+
+static void op_move_multiple(struct pointer *p) {
+    move_by(p, p->value);
+}
+
+static void op_read_value(struct machine *m, struct pointer *p) {
+    p->value = cell(m, p);
+}
+
+static void op_write_value(struct machine *m, struct pointer *p) {
+    grid_set(&m->grid, p->position, p->value);
+}
+
+static void op_write_value_move(struct machine *m, struct pointer *p) {
+    write_move(m, p, p->value);
+}
+
+static void op_swap_value(struct machine *m, struct pointer *p) {
+    uint8_t value = cell(m, p);
+    grid_set(&m->grid, p->position, p->value);
+    p->value = value;
+}
+
+static void op_push_position(struct pointer *p) {
+    if (p->stack_len == p->stack_capacity) {
+        p->stack_capacity = p->stack_capacity == 0 ? 16 : p->stack_capacity * 2;
+        p->stack = allocate(p->stack, p->stack_capacity * sizeof(struct position));
+    }
+    p->stack[p->stack_len++] = p->position;
+}
+
+static void op_pop_position(struct pointer *p) {
+    if (p->stack_len > 0) {
+        p->position = p->stack[--p->stack_len];
+    }
+}
+
+static void op_swap_position(struct pointer *p) {
+    if (p->stack_len > 0) {
+        struct position top = p->stack[p->stack_len - 1];
+        p->stack[p->stack_len - 1] = p->position;
+        p->position = top;
+    }
+}
+
+static void op_save_position(struct machine *m, struct pointer *p) {
+    m->saved[p->value] = p->position;
+    m->is_saved[p->value] = 1;
+}
+
+static void op_load_position(struct machine *m, struct pointer *p) {
+    if (m->is_saved[p->value]) {
+        p->position = m->saved[p->value];
+    }
+}
+
+static void op_value(struct pointer *p, uint8_t digit) {
+    p->value = (uint8_t)((p->value & 0x0f) << 4 | digit);
+}
+
+static void op_add(struct machine *m, struct pointer *p) {
+    p->value = (uint8_t)(p->value + cell(m, p));
+}
+
+static void op_subtract(struct machine *m, struct pointer *p) {
+    p->value = (uint8_t)(p->value - cell(m, p));
+}
+
+static void op_multiply(struct machine *m, struct pointer *p) {
+    p->value = (uint8_t)(p->value * cell(m, p));
+}
+
+static void op_divide(struct machine *m, struct pointer *p) {
+    uint8_t value = cell(m, p);
+    if (value == 0) {
+        fault(EXIT_RUNTIME_FAULT, "runtime fault: division by zero");
+    }
+    p->value = p->value / value;
+}
+
+static void op_modulo(struct machine *m, struct pointer *p) {
+    uint8_t value = cell(m, p);
+    if (value == 0) {
+        fault(EXIT_RUNTIME_FAULT, "runtime fault: modulo by zero");
+    }
+    p->value = p->value % value;
+}
+
+static void op_equals(struct machine *m, struct pointer *p) {
+    p->value = p->value == cell(m, p);
+}
+
+static void op_greater_than(struct machine *m, struct pointer *p) {
+    p->value = p->value > cell(m, p);
+}
+
+static void op_logical_and(struct machine *m, struct pointer *p) {
+    p->value = p->value != 0 && cell(m, p) != 0;
+}
+
+static void op_logical_or(struct machine *m, struct pointer *p) {
+    p->value = p->value != 0 || cell(m, p) != 0;
+}
+
+static void op_logical_not(struct pointer *p) {
+    p->value = p->value == 0;
+}
+
+static void op_random(struct machine *m, struct pointer *p) {
+    /* xorshift64 */
+    m->rng ^= m->rng << 13;
+    m->rng ^= m->rng >> 7;
+    m->rng ^= m->rng << 17;
+    p->value = (uint8_t)(m->rng >> 24);
+}
+
+static void op_write_character(struct machine *m, struct pointer *p) {
+    put_character(cell(m, p));
+}
+
+static void op_write_number(struct machine *m, struct pointer *p) {
+    printf("%u", (unsigned)cell(m, p));
+}
+
+static void op_write_string(struct machine *m, struct pointer *p) {
+    uint8_t value = cell(m, p);
+    while (value != 0) {
+        put_character(value);
+        move_by(p, 1);
+        value = cell(m, p);
+    }
+}
+
+enum read_kind { READ_CHARACTER, READ_NUMBER, READ_STRING };
+
+/* read a line of input, and write what it holds under the pointer */
+static void op_read(struct machine *m, struct pointer *p, enum read_kind kind) {
+    static uint8_t *line = NULL;
+    static uint32_t *characters = NULL;
+    static size_t capacity = 0;
+    size_t length = 0;
+    long count, start = 0, end, i;
+    int c;
+
+    fflush(stdout);
+
+    do {
+        c = getchar();
+        if (c == EOF) {
+            break;
+        }
+        if (length == capacity) {
+            capacity = capacity == 0 ? 256 : capacity * 2;
+            line = allocate(line, capacity);
+            characters = allocate(characters, capacity * sizeof(uint32_t));
+        }
+        line[length++] = (uint8_t)c;
+    } while (c != '\n');
+
+    if (ferror(stdin)) {
+        fault(EXIT_IO_ERROR, "program I/O failed: could not read input");
+    }
+
+    count = decode_utf8(line, length, characters);
+    if (count < 0) {
+        fault(EXIT_IO_ERROR, "program I/O failed: stream did not contain valid UTF-8");
+    }
+
+    end = count;
+    while (start < end && is_whitespace(characters[start])) {
+        start++;
+    }
+    while (end > start && is_whitespace(characters[end - 1])) {
+        end--;
+    }
+
+    switch (kind) {
+    case READ_CHARACTER:
+        write_move(m, p, start < end ? (uint8_t)characters[start] : 0);
+        break;
+    case READ_NUMBER: {
+        uint64_t number = 0;
+        int valid = start < end;
+
+        /* like parsing a `usize`, anything but a number reads as zero */
+        if (valid && characters[start] == '+') {
+            start++;
+            valid = start < end;
+        }
+        for (i = start; valid && i < end; i++) {
+            uint32_t digit = characters[i] - '0';
+            if (characters[i] < '0' || digit > 9 || number > (UINT64_MAX - digit) / 10) {
+                valid = 0;
+            } else {
+                number = number * 10 + digit;
+            }
+        }
+
+        write_move(m, p, valid ? (uint8_t)number : 0);
+        break;
+    }
+    case READ_STRING:
+        for (i = start; i < end; i++) {
+            write_move(m, p, (uint8_t)characters[i]);
+        }
+        break;
+    }
+}
+
+static struct pointer *push_pointer(struct machine *m) {
+    struct pointer *top, *pointer;
+
+    if (m->pointer_count == m->pointer_capacity) {
+        m->pointer_capacity = m->pointer_capacity == 0 ? 16 : m->pointer_capacity * 2;
+        m->pointers = allocate(m->pointers, m->pointer_capacity * sizeof(struct pointer));
+    }
+
+    pointer = &m->pointers[m->pointer_count];
+    memset(pointer, 0, sizeof(struct pointer));
+
+    /* a new pointer starts where the current one is, facing the same way */
+    if (m->pointer_count > 0) {
+        top = &m->pointers[m->pointer_count - 1];
+        pointer->position = top->position;
+        pointer->direction = top->direction;
+    }
+
+    m->pointer_count++;
+    return pointer;
+}
+
+static void pop_pointer(struct machine *m) {
+    m->pointer_count--;
+    free(m->pointers[m->pointer_count].stack);
+}
+
+/* run an instruction which is the same for the source code and the grid */
+static void execute(struct machine *m, struct pointer *p, uint8_t value) {
+    switch (value) {
+    case '>': op_set_direction(p, RIGHT); break;
+    case '<': op_set_direction(p, LEFT); break;
+    case '^': op_set_direction(p, UP); break;
+    case 'v': op_set_direction(p, DOWN); break;
+    case '.': move_by(p, 1); break;
+    case ':': op_move_multiple(p); break;
+    case '_': op_read_value(m, p); break;
+    case ',': op_write_value(m, p); break;
+    case ';': op_write_value_move(m, p); break;
+    case '~': op_swap_value(m, p); break;
+    case '(': op_push_position(p); break;
+    case ')': op_pop_position(p); break;
+    case '$': op_swap_position(p); break;
+    case 'q': op_save_position(m, p); break;
+    case 'g': op_load_position(m, p); break;
+    case '+': op_add(m, p); break;
+    case '-': op_subtract(m, p); break;
+    case '*': op_multiply(m, p); break;
+    case '/': op_divide(m, p); break;
+    case '%': op_modulo(m, p); break;
+    case '=': op_equals(m, p); break;
+    case '`': op_greater_than(m, p); break;
+    case '&': op_logical_and(m, p); break;
+    case '|': op_logical_or(m, p); break;
+    case '!': op_logical_not(p); break;
+    case '?': op_random(m, p); break;
+    case 'x': op_write_character(m, p); break;
+    case 'n': op_write_number(m, p); break;
+    case 's': op_write_string(m, p); break;
+    case 'X': op_read(m, p, READ_CHARACTER); break;
+    case 'N': op_read(m, p, READ_NUMBER); break;
+    case 'S': op_read(m, p, READ_STRING); break;
+    default:
+        if (value >= '0' && value <= '9') {
+            op_value(p, value - '0');
+        } else if (value >= 'A' && value <= 'F') {
+            op_value(p, value - 'A' + 10);
+        }
+    }
+}
+
+/* read the next value of the grid code which `head` is reading */
+static uint8_t tape_next(struct machine *m, struct pointer *head) {
+    uint8_t value = cell(m, head);
+    if (value != 0) {
+        move_by(head, 1);
+    }
+    return value;
+}
+
+static uint8_t tape_prev(struct machine *m, struct pointer *head) {
+    move_by(head, -1);
+    return cell(m, head);
+}
+
+/* jump from the loop bracket which `head` just read to its partner,
+   skipping brackets inside string literals */
+static void jump_loop(struct machine *m, struct pointer *head, int forwards) {
+    size_t depth = forwards ? 1 : 0;
+    uint8_t quote = 0;
+    uint8_t value;
+
+    while ((value = forwards ? tape_next(m, head) : tape_prev(m, head)) != 0) {
+        if (quote != 0) {
+            if (value == quote) {
+                quote = 0;
+            }
+        } else if (value == '\'' || value == '"') {
+            quote = value;
+        } else if (value == '[' || value == ']') {
+            if (value == (forwards ? ']' : '[')) {
+                if (depth > 1) {
+                    depth--;
+                } else {
+                    return;
+                }
+            } else {
+                depth++;
+            }
+        }
+    }
+
+    fault(EXIT_RUNTIME_FAULT, "runtime fault: unmatched loop bracket in evaluated code");
+}
+
+/* evaluate the grid code under the current pointer, until it is killed with
+   `@`. Returns 0 if the code ended first, which ends the whole program.
+   Unlike the rest, this is not static, as programs without `e` never call
+   it and should still compile without warnings. */
+int evaluate(struct machine *m) {
+    size_t depth = m->pointer_count;
+
+    push_pointer(m);
+
+    while (m->pointer_count > depth) {
+        /* every tape reads with the pointer below its own */
+        struct pointer *head = &m->pointers[m->pointer_count - 2];
+        struct pointer *p = &m->pointers[m->pointer_count - 1];
+        uint8_t value = tape_next(m, head);
+
+        if (value == 0) {
+            return 0;
+        }
+
+        if (m->string_mode != 0) {
+            if (value == m->string_mode) {
+                m->string_mode = 0;
+            } else {
+                write_move(m, p, value);
+            }
+            continue;
+        }
+
+        switch (value) {
+        case '[':
+            if (p->value == 0) {
+                jump_loop(m, head, 1);
+            }
+            break;
+        case ']':
+            if (p->value != 0) {
+                jump_loop(m, head, 0);
+            }
+            break;
+        case '\'':
+        case '"':
+            m->string_mode = value;
+            break;
+        case 'e':
+            push_pointer(m);
+            break;
+        case '@':
+            pop_pointer(m);
+            break;
+        default:
+            execute(m, p, value);
+        }
+    }
+
+    return 1;
+}
@@ -0,0 +1,129 @@
+use std::fmt::Write;
+
+use crate::*;
+
+use super::{merge_steps, Step};
+
+/// The most bytes of a literal to write on one line.
+const LITERAL_LINE_LENGTH: usize = 32;
+
+/// Compile `program` into a Rust module which depends on this crate. The
+/// module has a `run` function, which reads from a `BufRead` and writes to a
+/// `Write` like [`Interpreter::run`], and a `run_with` function, which runs
+/// on a [`NativeState`] that can be inspected afterwards.
+pub fn compile_rust(program: &Program) -> String {
+    let mut out = String::new();
+
+    out.push_str(concat!(
+        "// compiled from gridloc source code\n",
+        "\n",
+        "use std::io::{BufRead, Write};\n",
+        "\n",
+        "use gridloc::*;\n",
+        "\n",
+        "/// Run the program, reading input from `reader` and writing output to `writer`.\n",
+        "pub fn run<R: BufRead, W: Write>(reader: &mut R, writer: &mut W) -> Result<(), Error> {\n",
+        "    run_with(&mut NativeState::<HashGrid>::new(), reader, writer)\n",
+        "}\n",
+        "\n",
+        "/// Run the program on `s`, which is left as the program finished with it.\n",
+        "#[allow(unreachable_code, unused_variables)]\n",
+        "pub fn run_with<G: Grid, R: BufRead, W: Write>(s: &mut NativeState<G>, reader: &mut R, writer: &mut W) -> Result<(), Error> {\n",
+    ));
+    block(&mut out, &merge_steps(&program.items), 1);
+    out.push_str("    Ok(())\n");
+    out.push_str("}\n");
+
+    out
+}
+
+fn block(out: &mut String, steps: &[Step], depth: usize) {
+    let indent = "    ".repeat(depth);
+
+    for step in steps {
+        match step {
+            Step::Instruction(instruction) => {
+                for line in statement(*instruction) {
+                    writeln!(out, "{}{}", indent, line).unwrap();
+                }
+            }
+            Step::Move(count) => writeln!(out, "{}s.move_by({});", indent, count).unwrap(),
+            Step::SetValue(value) => writeln!(out, "{}s.pointer.value = {};", indent, value).unwrap(),
+            Step::Loop(body) => {
+                writeln!(out, "{}while s.pointer.value != 0 {{", indent).unwrap();
+                block(out, body, depth + 1);
+                writeln!(out, "{}}}", indent).unwrap();
+            }
+            Step::Literal([]) => {}
+            Step::Literal(bytes) => {
+                write!(out, "{}s.literal(", indent).unwrap();
+
+                // split long literals over several lines, with a backslash
+                // before each line break so that it is not part of the string
+                for (index, chunk) in bytes.chunks(LITERAL_LINE_LENGTH).enumerate() {
+                    if index == 0 {
+                        out.push_str("b\"");
+                    } else {
+                        write!(out, "\\\n{}    ", indent).unwrap();
+                    }
+
+                    out.push_str(&escape_rust(chunk, index > 0));
+                }
+
+                out.push_str("\")?;\n");
+            }
+        }
+    }
+}
+
+/// The Rust statements which run `instruction` with the source code's
+/// pointer.
+fn statement(instruction: Instruction) -> Vec<String> {
+    use Instruction::*;
+
+    match instruction {
+        SetDirection(direction) => vec![format!("s.pointer.direction = Direction::{:?};", direction)],
+        Evaluate => vec![
+            // the code ends the program if it is not killed first
+            "if !s.evaluate(reader, writer)? {".to_string(),
+            "    return Ok(());".to_string(),
+            "}".to_string(),
+        ],
+        Kill => vec!["return Ok(());".to_string()],
+        // loops and literals are steps of their own
+        StartLoop | EndLoop | ToggleStringMode { .. } => unreachable!(),
+        _ => vec![format!("s.execute({}, reader, writer)?;", instruction_path(instruction))],
+    }
+}
+
+/// The Rust expression for `instruction`.
+fn instruction_path(instruction: Instruction) -> String {
+    use Instruction::*;
+
+    match instruction {
+        SetDirection(direction) => format!("Instruction::SetDirection(Direction::{:?})", direction),
+        Value(value) => format!("Instruction::Value({})", value),
+        Write { kind } => format!("Instruction::Write {{ kind: IOKind::{:?} }}", kind),
+        Read { kind } => format!("Instruction::Read {{ kind: IOKind::{:?} }}", kind),
+        ToggleStringMode { kind } => format!("Instruction::ToggleStringMode {{ kind: StringModeKind::{:?} }}", kind),
+        _ => format!("Instruction::{:?}", instruction),
+    }
+}
+
+/// Escape `bytes` for the inside of a Rust byte string literal. A
+/// `continued` line has a leading space escaped, as it would be skipped
+/// after the backslash which ends the line before.
+fn escape_rust(bytes: &[u8], continued: bool) -> String {
+    let mut escaped = String::with_capacity(bytes.len());
+
+    for (index, &byte) in bytes.iter().enumerate() {
+        match byte {
+            b' ' if continued && index == 0 => escaped.push_str("\\x20"),
+            b'"' | b'\\' => write!(escaped, "\\{}", byte as char).unwrap(),
+            b' '..=b'~' => escaped.push(byte as char),
+            _ => write!(escaped, "\\x{:02x}", byte).unwrap(),
+        }
+    }
+
+    escaped
+}
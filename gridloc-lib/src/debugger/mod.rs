@@ -0,0 +1,191 @@
+use std::collections::BTreeMap;
+use std::mem;
+
+use crate::*;
+
+/// A condition on which the [`Debugger`] stops the program.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Breakpoint {
+    /// Stop before the value at this location of any eval tape is executed.
+    Tape(TapeLocation),
+    /// Stop after an instruction reads this grid cell.
+    CellRead(Position),
+    /// Stop after this grid cell is written to.
+    CellWritten(Position),
+    /// Stop before an instruction of this variant is executed. Fields of the
+    /// variant (such as the direction of `SetDirection`) are ignored.
+    Instruction(Instruction),
+    /// Stop after a position is saved, optionally only for one slot.
+    PositionSaved(Option<u8>),
+}
+
+impl Breakpoint {
+    /// Whether this breakpoint is hit by the step which is about to run.
+    fn hit_before<G: Grid, R: rand::Rng>(&self, state: &ProgramState<G, R>) -> bool {
+        match self {
+            Breakpoint::Tape(location) => state.current_tape()
+                .is_some_and(|tape| tape.location() == *location),
+            Breakpoint::Instruction(instruction) => state.peek_instruction()
+                .is_some_and(|next| mem::discriminant(&next) == mem::discriminant(instruction)),
+            _ => false,
+        }
+    }
+
+    /// Whether this breakpoint was hit by the step which just ran.
+    fn hit_after(&self, events: &[Event]) -> bool {
+        events.iter().any(|event| match (self, event) {
+            (Breakpoint::CellRead(target), Event::CellRead { position, .. }) => target == position,
+            (Breakpoint::CellWritten(target), Event::CellWritten { position, .. }) => target == position,
+            (Breakpoint::PositionSaved(target), Event::PositionSaved { slot, .. }) => {
+                target.is_none_or(|target| target == *slot)
+            }
+            _ => false,
+        })
+    }
+}
+
+/// Why the [`Debugger`] handed control back.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum StopReason {
+    /// A single step was run.
+    Step,
+    /// The breakpoint with this id was hit.
+    Breakpoint(usize),
+    /// The program has finished.
+    Finished,
+}
+
+/// Runs an [`Interpreter`] step by step, stopping on breakpoints.
+pub struct Debugger<'a, G: 'a + Grid> {
+    interpreter: Interpreter<'a, G>,
+    breakpoints: BTreeMap<usize, Breakpoint>,
+    next_id: usize,
+    finished: bool,
+}
+
+impl<'a, G: 'a + Grid> Debugger<'a, G> {
+    pub fn new(interpreter: Interpreter<'a, G>) -> Self {
+        Self {
+            interpreter,
+            breakpoints: BTreeMap::new(),
+            next_id: 1,
+            finished: false,
+        }
+    }
+
+    pub fn interpreter(&self) -> &Interpreter<'a, G> {
+        &self.interpreter
+    }
+
+    pub fn interpreter_mut(&mut self) -> &mut Interpreter<'a, G> {
+        &mut self.interpreter
+    }
+
+    /// The number of steps which have been run so far, as the program
+    /// counts them.
+    pub fn steps(&self) -> u64 {
+        self.interpreter.state().steps()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Add a breakpoint, returning its id.
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        self.breakpoints.insert(id, breakpoint);
+        id
+    }
+
+    pub fn remove_breakpoint(&mut self, id: usize) -> Option<Breakpoint> {
+        self.breakpoints.remove(&id)
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = (usize, &Breakpoint)> {
+        self.breakpoints.iter().map(|(id, breakpoint)| (*id, breakpoint))
+    }
+
+    /// Run a single step, regardless of any breakpoints before it. The
+    /// program is finished once a step fails.
+    pub fn step(&mut self) -> Result<StopReason, Error> {
+        if self.finished {
+            return Ok(StopReason::Finished);
+        }
+
+        let result = self.interpreter.step();
+        if !matches!(result, Ok(true)) {
+            self.finished = true;
+        }
+
+        if !result? {
+            return Ok(StopReason::Finished);
+        }
+
+        match self.breakpoint_after() {
+            Some(id) => Ok(StopReason::Breakpoint(id)),
+            None => Ok(StopReason::Step),
+        }
+    }
+
+    /// Undo the most recent step, if the interpreter records its history.
+    /// Returns `false` if there is no step to undo.
+    pub fn step_back(&mut self) -> bool {
+        if !self.interpreter.step_back() {
+            return false;
+        }
+
+        self.finished = false;
+        true
+    }
+
+    /// Step back to just before the most recent write to `position`,
+    /// returning the number of steps which were undone.
+    pub fn reverse_to_last_write(&mut self, position: &Position) -> Option<usize> {
+        let steps = self.interpreter.reverse_to_last_write(position)?;
+        if steps > 0 {
+            self.finished = false;
+        }
+
+        Some(steps)
+    }
+
+    /// Run until a breakpoint is hit or the program finishes. A breakpoint
+    /// on the very next step does not stop the program, so that continuing
+    /// from a breakpoint makes progress.
+    pub fn resume(&mut self) -> Result<StopReason, Error> {
+        let mut first = true;
+
+        loop {
+            if !first {
+                if let Some(id) = self.breakpoint_before() {
+                    return Ok(StopReason::Breakpoint(id));
+                }
+            }
+            first = false;
+
+            match self.step()? {
+                StopReason::Step => {}
+                reason => return Ok(reason),
+            }
+        }
+    }
+
+    fn breakpoint_before(&self) -> Option<usize> {
+        let state = self.interpreter.state();
+
+        self.breakpoints.iter()
+            .find(|(_, breakpoint)| breakpoint.hit_before(state))
+            .map(|(id, _)| *id)
+    }
+
+    fn breakpoint_after(&self) -> Option<usize> {
+        let events = self.interpreter.state().last_events();
+
+        self.breakpoints.iter()
+            .find(|(_, breakpoint)| breakpoint.hit_after(events))
+            .map(|(id, _)| *id)
+    }
+}
@@ -0,0 +1,187 @@
+use std::error;
+use std::fmt;
+use std::io;
+
+use crate::*;
+
+/// An error which stopped a program.
+#[derive(Debug)]
+pub enum Error {
+    /// Reading input or writing output failed.
+    Io(io::Error),
+    /// An instruction needed a pointer, but the pointer stack was empty.
+    PointerStackUnderflow { location: Location },
+    /// The eval tape could not be evaluated any further.
+    InvalidTape { fault: TapeFault, location: Location },
+    /// An arithmetic instruction had no defined result.
+    Arithmetic { fault: ArithmeticFault, location: Location },
+    /// The program exceeded one of its [`Limits`].
+    ResourceLimit { limit: Limit, location: Location },
+    /// An instruction was given to something which does not run it, such
+    /// as a loop bracket to [`NativeState::execute`], as compiled code runs
+    /// those itself.
+    Unsupported { instruction: Instruction, location: Location },
+}
+
+impl Error {
+    /// Where in the program the error occurred, unless it was an I/O error.
+    pub fn location(&self) -> Option<&Location> {
+        match self {
+            Error::Io(_) => None,
+            Error::PointerStackUnderflow { location }
+                | Error::InvalidTape { location, .. }
+                | Error::Arithmetic { location, .. }
+                | Error::ResourceLimit { location, .. }
+                | Error::Unsupported { location, .. } => Some(location),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(error) => write!(f, "I/O error: {}", error),
+            Error::PointerStackUnderflow { location } => {
+                write!(f, "pointer stack underflow at {}", location)
+            }
+            Error::InvalidTape { fault, location } => write!(f, "{} at {}", fault, location),
+            Error::Arithmetic { fault, location } => write!(f, "{} at {}", fault, location),
+            Error::ResourceLimit { limit, location } => {
+                write!(f, "exceeded the {} at {}", limit, location)
+            }
+            Error::Unsupported { instruction, location } => {
+                write!(f, "`{}` can not be run here at {}", instruction_char(*instruction), location)
+            }
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Error::Io(error)
+    }
+}
+
+/// The state of the program at the start of the step which failed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Location {
+    /// Depth of the eval tape, where 0 is the source code.
+    pub depth: usize,
+    /// Where the failing value was read from the eval tape.
+    pub tape: TapeLocation,
+    /// Position of the current pointer, if there was one.
+    pub pointer: Option<Position>,
+    /// Where the failing value came from in the original source code, if it
+    /// was read from the source.
+    pub source: Option<SourcePosition>,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.source {
+            Some(source) => write!(f, "{} (depth {})", source, self.depth)?,
+            None => write!(f, "{} (depth {})", self.tape, self.depth)?,
+        }
+
+        if let Some(pointer) = self.pointer {
+            write!(f, " with the pointer at {}", pointer)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A program which could not be built into an interpreter.
+#[derive(Debug)]
+pub enum BuildError {
+    /// Some loop brackets have no partner.
+    UnmatchedBrackets(Vec<UnmatchedBracket>),
+    /// The snapshot to resume from can not be resumed.
+    Snapshot(SnapshotError),
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BuildError::UnmatchedBrackets(brackets) => {
+                write!(f, "unmatched loop brackets:")?;
+
+                for bracket in brackets {
+                    write!(f, "\n  {}", bracket)?;
+                }
+
+                Ok(())
+            }
+            BuildError::Snapshot(error) => write!(f, "invalid snapshot: {}", error),
+        }
+    }
+}
+
+impl error::Error for BuildError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            BuildError::Snapshot(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+/// A `[` or `]` without a partner.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct UnmatchedBracket {
+    pub bracket: char,
+    /// Offset into the cleaned source code.
+    pub offset: usize,
+    /// Position in the original source code.
+    pub position: SourcePosition,
+}
+
+impl fmt::Display for UnmatchedBracket {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "`{}` at {}", self.bracket, self.position)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TapeFault {
+    /// A loop was jumped over or repeated, but the tape ended before the
+    /// matching bracket was found.
+    UnmatchedLoop,
+}
+
+impl fmt::Display for TapeFault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TapeFault::UnmatchedLoop => write!(f, "unmatched loop bracket"),
+        }
+    }
+}
+
+/// Arithmetic which has no result. Dividing by zero is a fault for both `/`
+/// and `%`, rather than quietly giving some value, so that a program never
+/// goes on with a number which it did not compute.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ArithmeticFault {
+    /// `/` with a grid value of zero.
+    DivideByZero,
+    /// `%` with a grid value of zero.
+    ModuloByZero,
+}
+
+impl fmt::Display for ArithmeticFault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ArithmeticFault::DivideByZero => write!(f, "division by zero"),
+            ArithmeticFault::ModuloByZero => write!(f, "modulo by zero"),
+        }
+    }
+}
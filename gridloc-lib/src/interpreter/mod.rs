@@ -1,44 +1,112 @@
+use std::cell::RefCell;
 use std::io;
-use std::io::{BufRead, Write};
+use std::io::{BufRead, Read, Write};
+use std::rc::Rc;
 
-use rand::{rngs::ThreadRng, thread_rng};
+use rand::{rngs::StdRng, thread_rng, RngCore, SeedableRng};
 
 use crate::*;
 
 pub struct Interpreter<'a, G: 'a + Grid> {
-    reader: Box<dyn BufRead>,
+    reader: RewindReader,
     writer: Box<dyn Write>,
-    state: ProgramState<'a, G, ThreadRng>,
+    state: ProgramState<'a, G, Box<dyn RngCore>>,
 }
 
 impl<'a, G: 'a + Grid> Interpreter<'a, G> {
-    pub fn state(&self) -> &ProgramState<'a, G, ThreadRng> {
+    pub fn state(&self) -> &ProgramState<'a, G, Box<dyn RngCore>> {
         &self.state
     }
 
-    pub fn run(&mut self) -> io::Result<()> {
-        while self.step()? {}
+    pub fn state_mut(&mut self) -> &mut ProgramState<'a, G, Box<dyn RngCore>> {
+        &mut self.state
+    }
 
-        Ok(())
+    pub fn run(&mut self) -> Result<(), Error> {
+        self.run_while(|| true)
+    }
+
+    /// Run until the program finishes or `keep_going` returns `false`, which
+    /// it is asked before every step.
+    pub fn run_while<F: FnMut() -> bool>(&mut self, mut keep_going: F) -> Result<(), Error> {
+        self.state.run(&mut self.reader, &mut self.writer, &mut keep_going)
     }
 
-    pub fn step(&mut self) -> io::Result<bool> {
+    pub fn step(&mut self) -> Result<bool, Error> {
         self.state.step(&mut self.reader, &mut self.writer)
     }
+
+    /// Undo the most recent step, if history is being recorded (see
+    /// [`InterpreterBuilder::record_history`]). Input which the step read is
+    /// read again by the steps which follow, but its output stays written.
+    ///
+    /// Returns `false` if there is no step to undo.
+    pub fn step_back(&mut self) -> bool {
+        match self.state.step_back() {
+            Some(input) => {
+                self.reader.unread(input);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Step back to just before the most recent write to `position`,
+    /// returning the number of steps which were undone. Nothing is undone if
+    /// no step in the history wrote to it.
+    pub fn reverse_to_last_write(&mut self, position: &Position) -> Option<usize> {
+        let steps = self.state.steps_since_write(position)?;
+
+        for _ in 0..steps {
+            self.step_back();
+        }
+
+        Some(steps)
+    }
+
+    /// Capture the state of the program, so that it can be resumed with
+    /// [`InterpreterBuilder::from_snapshot`].
+    pub fn snapshot(&self) -> Snapshot {
+        self.state.snapshot()
+    }
+}
+
+enum ProgramSource {
+    Source(String),
+    Snapshot(Snapshot),
 }
 
 pub struct InterpreterBuilder<'a> {
-    source: &'a str,
+    program: ProgramSource,
     reader: Option<Box<dyn BufRead>>,
     writer: Option<Box<dyn Write>>,
+    rng: Option<Box<dyn RngCore>>,
+    limits: Limits,
+    history: Option<usize>,
+    tracer: Option<Box<dyn Tracer + 'a>>,
 }
 
 impl<'a> InterpreterBuilder<'a> {
-    pub fn from_source(source: &'a str) -> Self {
+    /// Run `source`, which is cleaned with [`clean_source_mapped`] first, so
+    /// that errors refer to lines and columns of the original.
+    pub fn from_source(source: &str) -> Self {
+        Self::from_program(ProgramSource::Source(source.to_owned()))
+    }
+
+    /// Resume a program from a snapshot of its state.
+    pub fn from_snapshot(snapshot: Snapshot) -> Self {
+        Self::from_program(ProgramSource::Snapshot(snapshot))
+    }
+
+    fn from_program(program: ProgramSource) -> Self {
         Self {
-            source,
+            program,
             reader: None,
             writer: None,
+            rng: None,
+            limits: Limits::default(),
+            history: None,
+            tracer: None,
         }
     }
 
@@ -52,14 +120,148 @@ impl<'a> InterpreterBuilder<'a> {
         self
     }
 
-    pub fn build<G: Grid + 'a>(self) -> Interpreter<'a, G> {
-        let tape = SourceTape::from(self.source);
-        let program_state = ProgramState::new(Box::new(tape), thread_rng());
+    /// Use `rng` as the source of randomness for `?`. By default, the
+    /// thread-local generator is used.
+    pub fn rng(mut self, rng: Box<dyn RngCore>) -> Self {
+        self.rng = Some(rng);
+        self
+    }
+
+    /// Seed the source of randomness for `?`, so that runs are reproducible
+    /// with the same version of the interpreter.
+    pub fn seed(self, seed: u64) -> Self {
+        self.rng(Box::new(StdRng::seed_from_u64(seed)))
+    }
+
+    /// Stop the program once it exceeds any of `limits`.
+    pub fn limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Record the last `limit` steps, so that they can be undone with
+    /// [`Interpreter::step_back`].
+    pub fn record_history(mut self, limit: usize) -> Self {
+        self.history = Some(limit);
+        self
+    }
+
+    /// Pass a record of every step to `tracer`.
+    pub fn tracer(mut self, tracer: Box<dyn Tracer + 'a>) -> Self {
+        self.tracer = Some(tracer);
+        self
+    }
+
+    /// Build the interpreter, checking that every loop bracket of the
+    /// source code has a partner, or that the snapshot can be resumed.
+    pub fn build<G: Grid + 'a>(self) -> Result<Interpreter<'a, G>, BuildError> {
+        self.build_on(G::default())
+    }
+
+    /// Build the interpreter like [`build`](Self::build), running the
+    /// program on `grid`, which should be empty. This is how a grid which is
+    /// not the default one is used, like a [`TorusGrid`] of another size.
+    pub fn build_on<G: Grid + 'a>(self, grid: G) -> Result<Interpreter<'a, G>, BuildError> {
+        let rng = self.rng.unwrap_or_else(|| Box::new(thread_rng()));
+        let mut program_state = match self.program {
+            ProgramSource::Source(source) => {
+                let (cleaned, source_map) = clean_source_mapped(&source);
+                let tape = SourceTape::new(cleaned, 0).with_source_map(source_map);
+                check_brackets(&tape)?;
+
+                let mut program_state = ProgramState::new(Box::new(tape), rng);
+                program_state.grid = Rc::new(RefCell::new(grid));
+                program_state
+            }
+            ProgramSource::Snapshot(snapshot) => {
+                ProgramState::from_snapshot_on(snapshot, grid, rng).map_err(BuildError::Snapshot)?
+            }
+        };
+        program_state.limits = self.limits;
+
+        if let Some(limit) = self.history {
+            program_state.record_history(limit);
+        }
+
+        if let Some(tracer) = self.tracer {
+            program_state.set_tracer(tracer);
+        }
 
-        Interpreter {
-            reader: self.reader.unwrap_or(Box::new(io::empty())),
+        Ok(Interpreter {
+            reader: RewindReader::new(self.reader.unwrap_or(Box::new(io::empty()))),
             writer: self.writer.unwrap_or(Box::new(io::sink())),
             state: program_state,
+        })
+    }
+}
+
+/// Fail if any loop bracket of `tape` has no partner.
+fn check_brackets(tape: &SourceTape) -> Result<(), BuildError> {
+    let source_map = tape.source_map.as_ref().expect("source tapes are built with a source map");
+    let unmatched = tape.bytecode.brackets().describe_unmatched(&tape.source, source_map);
+
+    if unmatched.is_empty() {
+        Ok(())
+    } else {
+        Err(BuildError::UnmatchedBrackets(unmatched))
+    }
+}
+
+/// A reader which input can be pushed back into, so that it is read again
+/// after the step which read it is undone.
+struct RewindReader {
+    inner: Box<dyn BufRead>,
+    unread: Vec<u8>,
+    index: usize,
+}
+
+impl RewindReader {
+    fn new(inner: Box<dyn BufRead>) -> Self {
+        Self {
+            inner,
+            unread: Vec::new(),
+            index: 0,
+        }
+    }
+
+    /// Push `input` back, so that it is read before anything else.
+    fn unread(&mut self, mut input: Vec<u8>) {
+        input.extend_from_slice(&self.unread[self.index..]);
+        self.unread = input;
+        self.index = 0;
+    }
+}
+
+impl Read for RewindReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let count = available.len().min(buf.len());
+        buf[..count].copy_from_slice(&available[..count]);
+
+        self.consume(count);
+        Ok(count)
+    }
+}
+
+impl BufRead for RewindReader {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.index < self.unread.len() {
+            Ok(&self.unread[self.index..])
+        } else {
+            self.inner.fill_buf()
+        }
+    }
+
+    fn consume(&mut self, amount: usize) {
+        if self.index < self.unread.len() {
+            self.index += amount;
+
+            if self.index >= self.unread.len() {
+                self.unread.clear();
+                self.index = 0;
+            }
+        } else {
+            self.inner.consume(amount);
         }
     }
 }
@@ -67,6 +269,27 @@ impl<'a> InterpreterBuilder<'a> {
 pub struct SourceTape {
     source: Vec<u8>,
     index: usize,
+    bytecode: Rc<Bytecode>,
+    source_map: Option<SourceMap>,
+}
+
+impl SourceTape {
+    /// Create a tape over `source` which reads from `index` next.
+    pub fn new(source: Vec<u8>, index: usize) -> Self {
+        Self {
+            bytecode: Rc::new(Bytecode::compile(&source)),
+            source,
+            index,
+            source_map: None,
+        }
+    }
+
+    /// Map the values of this tape back to the original source code, which
+    /// `source_map` was produced from by [`clean_source_mapped`].
+    pub fn with_source_map(mut self, source_map: SourceMap) -> Self {
+        self.source_map = Some(source_map);
+        self
+    }
 }
 
 impl EvalTape for SourceTape {
@@ -98,14 +321,59 @@ impl EvalTape for SourceTape {
             None
         }
     }
+
+    fn location(&self) -> TapeLocation {
+        TapeLocation::Source(self.index)
+    }
+
+    fn seek(&mut self, location: TapeLocation) {
+        if let TapeLocation::Source(index) = location {
+            self.index = index;
+        }
+    }
+
+    fn compiled(&self) -> Option<Rc<Bytecode>> {
+        Some(self.bytecode.clone())
+    }
+
+    fn source_position(&self, location: TapeLocation) -> Option<SourcePosition> {
+        match location {
+            TapeLocation::Source(offset) => self.source_map.as_ref()?.position(offset),
+            TapeLocation::Grid(_) => None,
+        }
+    }
+
+    fn source_map(&self) -> Option<&SourceMap> {
+        self.source_map.as_ref()
+    }
+
+    fn jump_loop(&mut self, forwards: bool) -> bool {
+        // the bracket which was just read is behind the index
+        let partner = self.bytecode.brackets().partner(self.index - 1);
+
+        match partner {
+            Some(partner) if forwards => self.index = partner + 1,
+            Some(partner) => self.index = partner,
+            // stop where scanning for it would have
+            None if forwards => self.index = self.source.len(),
+            None => self.index = 0,
+        }
+
+        partner.is_some()
+    }
+
+    fn snapshot(&self, _pointers: &[Rc<RefCell<Pointer>>]) -> TapeSnapshot {
+        TapeSnapshot::Source {
+            source: self.source.clone(),
+            index: self.index,
+            source_map: self.source_map.clone(),
+        }
+    }
 }
 
 impl From<&[u8]> for SourceTape {
     fn from(slice: &[u8]) -> SourceTape {
-        Self {
-            source: slice.to_owned(),
-            index: 0,
-        }
+        Self::new(slice.to_owned(), 0)
     }
 }
 
@@ -1,8 +1,25 @@
-#![feature(or_patterns)]
+//! The gridloc esoteric programming language: an interpreter, debugger,
+//! linter and compilers to C and Rust.
+//!
+//! The minimum supported Rust version is 1.82, on stable. Raising it is a
+//! breaking change, and is done along with the `rust-version` in the
+//! manifests of the workspace.
+
+mod compile;
+pub use compile::*;
+
+mod debugger;
+pub use debugger::*;
+
+mod error;
+pub use error::*;
 
 mod interpreter;
 pub use interpreter::*;
 
+mod lint;
+pub use lint::*;
+
 mod program;
 pub use program::*;
 
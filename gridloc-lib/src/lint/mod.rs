@@ -0,0 +1,426 @@
+use std::collections::BTreeSet;
+use std::fmt;
+
+use crate::*;
+
+/// Starts a comment which configures the linter, as in
+/// `# gridloc: allow(zero-move)`.
+pub const LINT_DIRECTIVE: &str = "gridloc:";
+
+/// The kinds of problem which [`lint`] looks for.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum LintId {
+    /// A `[` or `]` without a partner.
+    UnmatchedBracket,
+    /// A character which is not an instruction, and so does nothing.
+    IgnoredCharacter,
+    /// A `:` which always moves by zero.
+    ZeroMove,
+    /// Code after an `@` which always ends the program.
+    UnreachableCode,
+    /// A `g` of a slot which no `q` ever saves to.
+    UnsavedSlot,
+    /// A `)` or `$` while the position stack is always empty.
+    EmptyPositionStack,
+    /// A string literal which is never closed.
+    UnterminatedString,
+    /// A directive which names a lint that does not exist.
+    UnknownLint,
+}
+
+impl LintId {
+    pub const ALL: [LintId; 8] = [
+        LintId::UnmatchedBracket,
+        LintId::IgnoredCharacter,
+        LintId::ZeroMove,
+        LintId::UnreachableCode,
+        LintId::UnsavedSlot,
+        LintId::EmptyPositionStack,
+        LintId::UnterminatedString,
+        LintId::UnknownLint,
+    ];
+
+    /// The name of the lint in warnings and directives.
+    pub fn name(&self) -> &'static str {
+        match self {
+            LintId::UnmatchedBracket => "unmatched-bracket",
+            LintId::IgnoredCharacter => "ignored-character",
+            LintId::ZeroMove => "zero-move",
+            LintId::UnreachableCode => "unreachable-code",
+            LintId::UnsavedSlot => "unsaved-slot",
+            LintId::EmptyPositionStack => "empty-position-stack",
+            LintId::UnterminatedString => "unterminated-string",
+            LintId::UnknownLint => "unknown-lint",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<LintId> {
+        LintId::ALL.iter().copied().find(|id| id.name() == name)
+    }
+}
+
+impl fmt::Display for LintId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// A warning about the source code.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Lint {
+    pub id: LintId,
+    pub position: SourcePosition,
+    pub message: String,
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {} [{}]", self.position, self.message, self.id)
+    }
+}
+
+/// Look for likely mistakes in `source`, returning the warnings in the order
+/// of their position.
+///
+/// A warning is not reported if a comment on its line or the line before
+/// allows it with `# gridloc: allow(<lint>, ...)`, or if any comment allows
+/// it for the whole file with `# gridloc: allow-file(<lint>, ...)`.
+pub fn lint(source: &str) -> Vec<Lint> {
+    let (code, source_map) = clean_source_mapped(source);
+    let brackets = Brackets::new(&code);
+
+    let mut linter = Linter {
+        code: &code,
+        source_map: &source_map,
+        brackets: &brackets,
+        literal: vec![false; code.len()],
+        lints: Vec::new(),
+    };
+
+    linter.check_literals();
+    linter.check_brackets();
+    linter.check_characters();
+    linter.check_flow();
+
+    let Linter { mut lints, .. } = linter;
+    let directives = Directives::parse(&source_map, &mut lints);
+
+    lints.retain(|lint| !directives.allows(lint));
+    lints.sort_by_key(|lint| lint.position);
+    lints
+}
+
+struct Linter<'a> {
+    code: &'a [u8],
+    source_map: &'a SourceMap,
+    brackets: &'a Brackets,
+    /// Whether each value is part of a string literal, including its quotes.
+    literal: Vec<bool>,
+    lints: Vec<Lint>,
+}
+
+impl<'a> Linter<'a> {
+    fn position(&self, offset: usize) -> SourcePosition {
+        self.source_map.position(offset).unwrap_or(SourcePosition { line: 1, column: offset + 1 })
+    }
+
+    fn warn(&mut self, id: LintId, offset: usize, message: String) {
+        let position = self.position(offset);
+        self.lints.push(Lint { id, position, message });
+    }
+
+    /// Mark the string literals, and warn about one which is never closed.
+    fn check_literals(&mut self) {
+        let mut open: Option<usize> = None;
+
+        for (offset, &value) in self.code.iter().enumerate() {
+            match open {
+                Some(start) if self.code[start] == value => {
+                    self.literal[offset] = true;
+                    open = None;
+                }
+                Some(_) => self.literal[offset] = true,
+                None => {
+                    if let Some(Instruction::ToggleStringMode { .. }) = parse_instruction(value as char) {
+                        self.literal[offset] = true;
+                        open = Some(offset);
+                    }
+                }
+            }
+        }
+
+        if let Some(start) = open {
+            let message = "string literal is never closed, so the rest of the program is written to the grid instead of run"
+                .to_string();
+            self.warn(LintId::UnterminatedString, start, message);
+        }
+    }
+
+    fn check_brackets(&mut self) {
+        for &offset in self.brackets.unmatched() {
+            let message = format!("`{}` has no matching bracket", self.code[offset] as char);
+            self.warn(LintId::UnmatchedBracket, offset, message);
+        }
+    }
+
+    /// Warn about every character outside of literals which is not an
+    /// instruction.
+    fn check_characters(&mut self) {
+        let mut offset = 0;
+
+        while offset < self.code.len() {
+            if self.literal[offset] {
+                offset += 1;
+                continue;
+            }
+
+            // code outside of literals comes straight from the source, so it
+            // is valid UTF-8
+            let width = match self.code[offset] {
+                0xf0..=0xff => 4,
+                0xe0..=0xef => 3,
+                0xc0..=0xdf => 2,
+                _ => 1,
+            };
+
+            let end = (offset + width).min(self.code.len());
+            let c = std::str::from_utf8(&self.code[offset..end])
+                .ok()
+                .and_then(|s| s.chars().next())
+                .unwrap_or(char::REPLACEMENT_CHARACTER);
+
+            if !c.is_whitespace() && parse_instruction(c).is_none() {
+                let message = format!("`{}` is not an instruction, so it is ignored", c.escape_debug());
+                self.warn(LintId::IgnoredCharacter, offset, message);
+            }
+
+            offset += width;
+        }
+    }
+
+    /// Follow what is known about the pointer through the program.
+    fn check_flow(&mut self) {
+        let mut flow = Flow::default();
+        let mut lints = Vec::new();
+
+        self.check_block(0, self.code.len(), State::START, 0, &mut flow, &mut lints);
+        self.lints.extend(lints);
+
+        // code run with `e` can save to any slot
+        if flow.unknown_save || flow.evaluates {
+            return;
+        }
+
+        for (offset, slot) in flow.loads {
+            if !flow.saved.contains(&slot) {
+                let message = format!("`g` loads slot {}, which is never saved to with `q`", slot);
+                self.warn(LintId::UnsavedSlot, offset, message);
+            }
+        }
+    }
+
+    /// Follow the code from `start` up to `end` in `state`, returning the
+    /// state after it. `depth` is the number of loops around the code.
+    fn check_block(&self, start: usize, end: usize, mut state: State, depth: usize, flow: &mut Flow, lints: &mut Vec<Lint>) -> State {
+        use Instruction::*;
+
+        let mut offset = start;
+
+        while offset < end {
+            let value = self.code[offset];
+
+            if self.literal[offset] {
+                // literals are written to the grid, leaving the pointer value
+                // alone
+                offset += 1;
+                continue;
+            }
+
+            match parse_instruction(value as char) {
+                Some(StartLoop) => {
+                    let close = match self.brackets.partner(offset) {
+                        Some(close) => close,
+                        None => {
+                            state = State::UNKNOWN;
+                            offset += 1;
+                            continue;
+                        }
+                    };
+
+                    // a loop which is never entered does nothing
+                    if state.value != Some(0) {
+                        let entry = State { value: None, nibble: None, ..state };
+                        let mut body_lints = Vec::new();
+                        let exit = self.check_block(offset + 1, close, entry, depth + 1, flow, &mut body_lints);
+
+                        // the stack is only known on every iteration if the
+                        // body leaves it as it found it
+                        let balanced = exit.stack == entry.stack;
+                        if !balanced {
+                            body_lints.retain(|lint| lint.id != LintId::EmptyPositionStack);
+                        }
+                        lints.extend(body_lints);
+
+                        state.stack = if balanced { state.stack } else { None };
+                    }
+
+                    state.value = Some(0);
+                    state.nibble = Some(0);
+                    offset = close + 1;
+                    continue;
+                }
+                // the partner of a matched `]` is always skipped over above
+                Some(EndLoop) => state = State::UNKNOWN,
+                Some(Kill) if depth == 0 => {
+                    let next = (offset + 1..end).find(|&next| !(self.code[next] as char).is_whitespace());
+
+                    if let Some(next) = next {
+                        lints.push(Lint {
+                            id: LintId::UnreachableCode,
+                            position: self.position(next),
+                            message: "code after `@` is never run, as `@` ends the program".to_string(),
+                        });
+                    }
+
+                    return state;
+                }
+                Some(Value(digit)) => {
+                    state.value = state.nibble.map(|nibble| (nibble << 4) | digit);
+                    state.nibble = Some(digit);
+                }
+                Some(MoveMultiple) if state.value == Some(0) => {
+                    lints.push(Lint {
+                        id: LintId::ZeroMove,
+                        position: self.position(offset),
+                        message: "`:` always moves by zero, so it does nothing".to_string(),
+                    });
+                }
+                Some(PushPosition) => state.stack = state.stack.map(|depth| depth + 1),
+                Some(instruction @ (PopPosition | SwapPosition)) => match state.stack {
+                    Some(0) => {
+                        let c = if instruction == PopPosition { ')' } else { '$' };
+
+                        lints.push(Lint {
+                            id: LintId::EmptyPositionStack,
+                            position: self.position(offset),
+                            message: format!("`{}` does nothing, as the position stack is always empty here", c),
+                        });
+                    }
+                    Some(length) if instruction == PopPosition => state.stack = Some(length - 1),
+                    _ => {}
+                },
+                Some(SavePosition) => match state.value {
+                    Some(slot) => {
+                        flow.saved.insert(slot);
+                    }
+                    None => flow.unknown_save = true,
+                },
+                Some(LoadPosition) => {
+                    if let Some(slot) = state.value {
+                        flow.loads.push((offset, slot));
+                    }
+                }
+                // the code which is run has its own pointer
+                Some(Evaluate) => flow.evaluates = true,
+                Some(LogicalNot) => state.set_value(state.value.map(|value| (value == 0) as u8)),
+                Some(ReadValue | SwapValue | Add | Subtract | Multiply | Divide | Modulo | Equals
+                    | GreaterThan | LogicalAnd | LogicalOr | Random | Read { .. }) => state.set_value(None),
+                _ => {}
+            }
+
+            offset += 1;
+        }
+
+        state
+    }
+}
+
+/// What is known about the current pointer at some point of the program.
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct State {
+    value: Option<u8>,
+    /// The lower four bits of the value, which a digit keeps.
+    nibble: Option<u8>,
+    /// Length of the position stack.
+    stack: Option<usize>,
+}
+
+impl State {
+    const START: State = State { value: Some(0), nibble: Some(0), stack: Some(0) };
+    const UNKNOWN: State = State { value: None, nibble: None, stack: None };
+
+    fn set_value(&mut self, value: Option<u8>) {
+        self.value = value;
+        self.nibble = value.map(|value| value & 0b1111);
+    }
+}
+
+/// What is known about the whole program.
+#[derive(Default)]
+struct Flow {
+    /// Slots which some `q` saves to.
+    saved: BTreeSet<u8>,
+    /// Whether some `q` saves to a slot which is not known.
+    unknown_save: bool,
+    /// Every `g` of a known slot.
+    loads: Vec<(usize, u8)>,
+    /// Whether the program runs code from the grid.
+    evaluates: bool,
+}
+
+/// The lints which comments of the source code allow.
+#[derive(Default)]
+struct Directives {
+    /// Lines where each lint is allowed.
+    lines: BTreeSet<(LintId, usize)>,
+    file: BTreeSet<LintId>,
+}
+
+impl Directives {
+    /// Read the directives of the comments in `source_map`, warning about
+    /// lints they name which do not exist.
+    fn parse(source_map: &SourceMap, lints: &mut Vec<Lint>) -> Self {
+        let mut directives = Directives::default();
+
+        for comment in source_map.comments() {
+            let rest = match comment.text.trim().strip_prefix(LINT_DIRECTIVE) {
+                Some(rest) => rest.trim(),
+                None => continue,
+            };
+
+            let (whole_file, names) = if let Some(names) = rest.strip_prefix("allow-file(") {
+                (true, names)
+            } else if let Some(names) = rest.strip_prefix("allow(") {
+                (false, names)
+            } else {
+                continue;
+            };
+
+            let names = names.trim_end().trim_end_matches(')');
+
+            for name in names.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+                match LintId::from_name(name) {
+                    Some(id) if whole_file => {
+                        directives.file.insert(id);
+                    }
+                    Some(id) => {
+                        // a directive covers its own line and the next one
+                        directives.lines.insert((id, comment.position.line));
+                        directives.lines.insert((id, comment.position.line + 1));
+                    }
+                    None => lints.push(Lint {
+                        id: LintId::UnknownLint,
+                        position: comment.position,
+                        message: format!("there is no lint named `{}`", name),
+                    }),
+                }
+            }
+        }
+
+        directives
+    }
+
+    fn allows(&self, lint: &Lint) -> bool {
+        self.file.contains(&lint.id) || self.lines.contains(&(lint.id, lint.position.line))
+    }
+}
@@ -0,0 +1,260 @@
+use std::io::{BufRead, Write};
+
+use crate::*;
+
+/// The source code of a program, compiled ahead of time so that it can run
+/// without decoding every value and scanning for the ends of loops.
+///
+/// Only the parts of the source which are always executed the same way are
+/// compiled. String literals, unmatched brackets and any value which is
+/// jumped into the middle of are left to the interpreter.
+#[derive(Clone, Debug)]
+pub struct Bytecode {
+    ops: Vec<Op>,
+    /// The op starting at each source offset, if any.
+    starts: Vec<Option<usize>>,
+    brackets: Brackets,
+}
+
+/// A run of one or more values of the source code.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Op {
+    pub kind: OpKind,
+    /// The offset of the first value.
+    pub start: usize,
+    /// The number of values, each of which counts as a step.
+    pub len: usize,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum OpKind {
+    /// Values which are not instructions.
+    Nop,
+    /// Consecutive `.` instructions.
+    Move(usize),
+    /// Two or more consecutive digits, which leave the pointer value set to
+    /// the last two of them.
+    SetValue(u8),
+    /// `[`, which jumps to the offset after its `]` if the pointer value is
+    /// zero.
+    JumpIfZero(usize),
+    /// `]`, which jumps back to the offset of its `[` if the pointer value is
+    /// not zero.
+    JumpIfNonZero(usize),
+    /// Any other instruction.
+    Instruction(Instruction),
+}
+
+impl Bytecode {
+    pub fn compile(source: &[u8]) -> Self {
+        use Instruction::*;
+
+        let brackets = Brackets::new(source);
+        let mut ops = Vec::new();
+        let mut starts = vec![None; source.len() + 1];
+
+        let mut index = 0;
+        while index < source.len() {
+            let instruction = parse_instruction(source[index] as char);
+            let run = |matches: fn(Option<Instruction>) -> bool| {
+                source[index..].iter()
+                    .take_while(|value| matches(parse_instruction(**value as char)))
+                    .count()
+            };
+
+            let (kind, len) = match instruction {
+                Some(ToggleStringMode { .. }) => {
+                    // leave the literal, including its quotes, to the
+                    // interpreter
+                    let quote = source[index];
+                    index = source[index + 1..].iter()
+                        .position(|value| *value == quote)
+                        .map_or(source.len(), |offset| index + offset + 2);
+                    continue;
+                }
+                Some(StartLoop) => match brackets.partner(index) {
+                    Some(end) => (OpKind::JumpIfZero(end + 1), 1),
+                    None => {
+                        index += 1;
+                        continue;
+                    }
+                },
+                Some(EndLoop) => match brackets.partner(index) {
+                    Some(start) => (OpKind::JumpIfNonZero(start), 1),
+                    None => {
+                        index += 1;
+                        continue;
+                    }
+                },
+                Some(MoveOne) => {
+                    let len = run(|instruction| instruction == Some(MoveOne));
+                    (OpKind::Move(len), len)
+                }
+                Some(Value(_)) => {
+                    let len = run(|instruction| matches!(instruction, Some(Value(_))));
+
+                    if len > 1 {
+                        let digit = |offset: usize| match parse_instruction(source[offset] as char) {
+                            Some(Value(value)) => value,
+                            _ => unreachable!(),
+                        };
+                        let end = index + len;
+
+                        (OpKind::SetValue(digit(end - 2) << 4 | digit(end - 1)), len)
+                    } else {
+                        (OpKind::Instruction(instruction.unwrap()), 1)
+                    }
+                }
+                Some(instruction) => (OpKind::Instruction(instruction), 1),
+                None => {
+                    let len = run(|instruction| instruction.is_none());
+                    (OpKind::Nop, len)
+                }
+            };
+
+            starts[index] = Some(ops.len());
+            ops.push(Op { kind, start: index, len });
+            index += len;
+        }
+
+        Self {
+            ops,
+            starts,
+            brackets,
+        }
+    }
+
+    pub fn ops(&self) -> &[Op] {
+        &self.ops
+    }
+
+    /// The op which starts at `offset`, if any.
+    pub fn op_at(&self, offset: usize) -> Option<&Op> {
+        self.starts.get(offset)
+            .copied()
+            .flatten()
+            .map(|index| &self.ops[index])
+    }
+
+    /// The loop brackets of the source code.
+    pub fn brackets(&self) -> &Brackets {
+        &self.brackets
+    }
+}
+
+impl<'a, G: 'a + Grid, Rng: rand::Rng> ProgramState<'a, G, Rng> {
+    /// Run until the program finishes or `keep_going` returns `false`, which
+    /// it is asked before every step. Compiled source code is run directly
+    /// whenever nothing needs to observe the individual steps.
+    ///
+    /// The tracer is flushed before returning, though an error of the
+    /// program takes precedence over one of flushing.
+    pub fn run<R: BufRead, W: Write>(&mut self, reader: &mut R, writer: &mut W, keep_going: &mut dyn FnMut() -> bool) -> Result<(), Error> {
+        let result = self.run_steps(reader, writer, keep_going);
+        let flushed = self.flush_tracer();
+
+        result.and(flushed)
+    }
+
+    fn run_steps<R: BufRead, W: Write>(&mut self, reader: &mut R, writer: &mut W, keep_going: &mut dyn FnMut() -> bool) -> Result<(), Error> {
+        loop {
+            if self.tracer.is_none() && self.history.is_none() && !self.run_compiled(reader, writer, keep_going)? {
+                return Ok(());
+            }
+
+            // the compiled code could not go on, so take a step without it
+            if !keep_going() || !self.step(reader, writer)? {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Run ops of the compiled source tape for as long as possible. Returns
+    /// `false` if `keep_going` asked to stop.
+    fn run_compiled<R: BufRead, W: Write>(&mut self, reader: &mut R, writer: &mut W, keep_going: &mut dyn FnMut() -> bool) -> Result<bool, Error> {
+        // only the source tape at the bottom of the stack is compiled
+        if self.eval_tapes.len() != 1 || self.string_mode.is_some() {
+            return Ok(true);
+        }
+
+        let (bytecode, mut offset) = match (self.eval_tapes[0].compiled(), self.eval_tapes[0].location()) {
+            (Some(bytecode), TapeLocation::Source(offset)) => (bytecode, offset),
+            _ => return Ok(true),
+        };
+
+        let pointer = match self.current_pointer() {
+            Some(pointer) => pointer,
+            None => return Ok(true),
+        };
+
+        let result = loop {
+            let op = match bytecode.op_at(offset) {
+                Some(op) => *op,
+                None => break Ok(true),
+            };
+
+            // leave the step which hits the limit to the interpreter
+            if self.limits.max_steps.is_some_and(|max| self.steps + op.len as u64 > max) {
+                break Ok(true);
+            }
+
+            if !keep_going() {
+                break Ok(false);
+            }
+
+            self.steps += op.len as u64;
+            offset = op.start + op.len;
+
+            match op.kind {
+                OpKind::Nop => {}
+                OpKind::Move(count) => {
+                    let mut pointer = pointer.borrow_mut();
+                    let position = pointer.position + pointer.direction.unit_vector() * count as isize;
+                    pointer.position = self.grid.borrow().wrap(position);
+                }
+                OpKind::SetValue(value) => {
+                    pointer.borrow_mut().value = value;
+                }
+                OpKind::JumpIfZero(target) => {
+                    if pointer.borrow().value == 0 {
+                        offset = target;
+                    }
+                }
+                OpKind::JumpIfNonZero(target) => {
+                    if pointer.borrow().value != 0 {
+                        offset = target;
+                    }
+                }
+                OpKind::Instruction(instruction) => {
+                    self.events.clear();
+                    self.location = Location {
+                        depth: 0,
+                        tape: TapeLocation::Source(op.start),
+                        pointer: Some(pointer.borrow().position),
+                        source: self.eval_tapes[0].source_position(TapeLocation::Source(op.start)),
+                    };
+
+                    let executed = self.execute_instruction(instruction, reader, writer);
+                    self.wrap_pointers();
+
+                    if let Err(error) = executed {
+                        break Err(error);
+                    }
+
+                    // the instruction may have changed what runs next
+                    if self.eval_tapes.len() != 1 || self.string_mode.is_some() {
+                        break Ok(true);
+                    }
+                }
+            }
+        };
+
+        // `@` may have removed the source tape, but otherwise it is still at
+        // the bottom of the stack
+        if let Some(tape) = self.eval_tapes.first_mut() {
+            tape.seek(TapeLocation::Source(offset));
+        }
+
+        result
+    }
+}
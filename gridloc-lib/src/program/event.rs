@@ -0,0 +1,23 @@
+use crate::*;
+
+/// Something which a step did to the state of the program.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Event {
+    /// An instruction read the value of a grid cell.
+    CellRead {
+        position: Position,
+        value: u8,
+    },
+    /// A grid cell was written to.
+    CellWritten {
+        position: Position,
+        old: u8,
+        new: u8,
+    },
+    /// A position was saved to a slot.
+    PositionSaved {
+        slot: u8,
+        old: Option<Position>,
+        new: Position,
+    },
+}
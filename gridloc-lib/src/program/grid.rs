@@ -1,6 +1,9 @@
-use ndarray::Array2;
+use ndarray::{s, Array2};
 
+use std::collections::hash_map::RandomState;
 use std::collections::HashMap;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 use crate::*;
 
@@ -8,27 +11,162 @@ type ChunkCoord = (isize, isize);
 type ChunkOffset = (usize, usize);
 
 const CHUNK_LENGTH: usize = 8;
-const CHUNK_SIZE: usize = CHUNK_LENGTH * CHUNK_LENGTH;
 
 pub trait Grid: Default {
     fn get(&self, pos: &Position) -> u8;
-    fn set(&mut self, pos: &Position, value: u8) -> u8;
+    /// Set the cell at `pos` to `value`, returning its old value. Returns
+    /// `None` and changes nothing if the grid would have to grow past the
+    /// largest size it may have. Grids which can hold any cell never fail,
+    /// and have a `set` method which can not fail as well.
+    fn try_set(&mut self, pos: &Position, value: u8) -> Option<u8>;
+    /// The number of cells with a non-zero value.
+    fn cell_count(&self) -> usize;
+    /// All cells with a non-zero value, in no particular order.
+    fn cells(&self) -> Box<dyn Iterator<Item = (Position, u8)> + '_>;
+    /// The position of the cell which `pos` refers to, which is `pos` itself
+    /// unless the grid wraps around.
+    fn wrap(&self, pos: Position) -> Position {
+        pos
+    }
+    /// The number of cells in `direction` after which a pointer is back
+    /// where it started, or `None` unless the grid wraps around.
+    fn lap(&self, _direction: Direction) -> Option<usize> {
+        None
+    }
+
+    /// The smallest and the largest corner of the smallest rectangle which
+    /// contains every non-zero cell, or `None` if there are none.
+    fn bounding_box(&self) -> Option<(Position, Position)> {
+        self.cells().fold(None, |bounds, (pos, _)| Some(match bounds {
+            Some((min, max)) => extend_bounds(min, max, pos, pos),
+            None => (pos, pos),
+        }))
+    }
+
+    /// The values of the `width` by `height` cells from `min`, by row and
+    /// then column.
+    fn region(&self, min: &Position, width: usize, height: usize) -> Array2<u8> {
+        Array2::from_shape_fn((height, width), |(row, column)| {
+            self.get(&(*min + Position::new(column as isize, row as isize)))
+        })
+    }
+
+    /// Write `values`, by row and then column, to the cells from `min`.
+    /// Zeros are written as well, clearing the cells under them.
+    ///
+    /// Returns `None` at the first cell which [`try_set`](Self::try_set)
+    /// fails on, leaving the cells before it written.
+    fn set_region(&mut self, min: &Position, values: &Array2<u8>) -> Option<()> {
+        for ((row, column), value) in values.indexed_iter() {
+            self.try_set(&(*min + Position::new(column as isize, row as isize)), *value)?;
+        }
+
+        Some(())
+    }
+
+    /// Set every cell to zero.
+    fn clear(&mut self) {
+        let positions: Vec<_> = self.cells().map(|(pos, _)| pos).collect();
+
+        for pos in positions {
+            // a cell which is there already never grows the grid
+            let _ = self.try_set(&pos, 0);
+        }
+    }
 }
 
+/// A grid of any size, stored in chunks of cells which are only kept while
+/// they have a non-zero cell.
 #[derive(Default)]
 pub struct HashGrid {
-    chunks: HashMap<ChunkCoord, Chunk>,
-    // TODO cache the last used chunk?
+    chunks: Vec<(ChunkCoord, Chunk)>,
+    /// The index of each chunk in `chunks`.
+    indices: HashMap<ChunkCoord, usize, ChunkHashState>,
+    cell_count: usize,
+    /// The index of the last chunk which was found, as pointers mostly move
+    /// within one chunk at a time. It is checked against the coordinate in
+    /// `chunks` before it is used, so it never has to be reset. Being atomic
+    /// keeps the grid `Sync`, and relaxed loads and stores cost no more than
+    /// plain ones.
+    last_chunk: AtomicUsize,
 }
 
 impl HashGrid {
-    fn get_chunk(&self, coord: &ChunkCoord) -> Option<&Chunk> {
-        self.chunks.get(coord)
+    /// Set the cell at `pos` to `value`, returning its old value.
+    pub fn set(&mut self, pos: &Position, value: u8) -> u8 {
+        let (chunk_coords, chunk_offset) = position_to_chunk(pos);
+
+        let index = match self.chunk_index(&chunk_coords) {
+            Some(index) => index,
+            // the cell is zero already
+            None if value == 0 => return 0,
+            None => self.insert_chunk(&chunk_coords),
+        };
+
+        let chunk = &mut self.chunks[index].1;
+        let old_value = chunk.set(&chunk_offset, value);
+
+        // remove the chunk if it is now empty
+        if chunk.is_empty() {
+            self.remove_chunk(index);
+        }
+
+        self.count_cell(old_value, value);
+
+        old_value
     }
 
-    fn get_chunk_mut(&mut self, coord: &ChunkCoord) -> &mut Chunk {
-        self.chunks.entry(*coord)
-            .or_default()
+    /// The index of the chunk at `coord`, if it has a non-zero cell.
+    fn chunk_index(&self, coord: &ChunkCoord) -> Option<usize> {
+        let last = self.last_chunk.load(Ordering::Relaxed);
+
+        match self.chunks.get(last) {
+            Some((last_coord, _)) if last_coord == coord => Some(last),
+            _ => {
+                let index = *self.indices.get(coord)?;
+                self.last_chunk.store(index, Ordering::Relaxed);
+                Some(index)
+            }
+        }
+    }
+
+    fn insert_chunk(&mut self, coord: &ChunkCoord) -> usize {
+        let index = self.chunks.len();
+        self.chunks.push((*coord, Chunk::default()));
+        self.indices.insert(*coord, index);
+        *self.last_chunk.get_mut() = index;
+
+        index
+    }
+
+    fn remove_chunk(&mut self, index: usize) {
+        let (coord, _) = self.chunks.swap_remove(index);
+        self.indices.remove(&coord);
+
+        // the last chunk was moved into its place
+        if let Some((moved_coord, _)) = self.chunks.get(index) {
+            self.indices.insert(*moved_coord, index);
+        }
+    }
+
+    /// Count a cell which changed from `old_value` to `value`.
+    fn count_cell(&mut self, old_value: u8, value: u8) {
+        if old_value == 0 && value != 0 {
+            self.cell_count += 1;
+        } else if old_value != 0 && value == 0 {
+            self.cell_count -= 1;
+        }
+    }
+}
+
+impl Clone for HashGrid {
+    fn clone(&self) -> Self {
+        Self {
+            chunks: self.chunks.clone(),
+            indices: self.indices.clone(),
+            cell_count: self.cell_count,
+            last_chunk: AtomicUsize::new(self.last_chunk.load(Ordering::Relaxed)),
+        }
     }
 }
 
@@ -36,30 +174,476 @@ impl Grid for HashGrid {
     fn get(&self, pos: &Position) -> u8 {
         let (chunk_coords, chunk_offset) = position_to_chunk(pos);
 
-        if let Some(chunk) = self.get_chunk(&chunk_coords) {
-            chunk.get(&chunk_offset)
+        match self.chunk_index(&chunk_coords) {
+            Some(index) => self.chunks[index].1.get(&chunk_offset),
+            None => 0,
+        }
+    }
+
+    fn try_set(&mut self, pos: &Position, value: u8) -> Option<u8> {
+        Some(self.set(pos, value))
+    }
+
+    fn cell_count(&self) -> usize {
+        self.cell_count
+    }
+
+    fn cells(&self) -> Box<dyn Iterator<Item = (Position, u8)> + '_> {
+        Box::new(self.chunks.iter().flat_map(|(coord, chunk)| {
+            chunk.cells.iter()
+                .enumerate()
+                .filter(|(_, value)| **value != 0)
+                .map(move |(index, value)| {
+                    let offset = (index % CHUNK_LENGTH, index / CHUNK_LENGTH);
+                    (chunk_to_position(coord, &offset), *value)
+                })
+        }))
+    }
+
+    fn bounding_box(&self) -> Option<(Position, Position)> {
+        let last = CHUNK_LENGTH as isize - 1;
+        let mut bounds: Option<(Position, Position)> = None;
+
+        for (coord, chunk) in &self.chunks {
+            let chunk_min = chunk_to_position(coord, &(0, 0));
+            let chunk_max = chunk_min + Position::new(last, last);
+
+            // a chunk within the bounds so far can not extend them
+            if let Some((min, max)) = bounds {
+                if min.x <= chunk_min.x && min.y <= chunk_min.y && chunk_max.x <= max.x && chunk_max.y <= max.y {
+                    continue;
+                }
+            }
+
+            let (cells_min, cells_max) = chunk.bounding_box();
+            let cells_min = chunk_to_position(coord, &cells_min);
+            let cells_max = chunk_to_position(coord, &cells_max);
+
+            bounds = Some(match bounds {
+                Some((min, max)) => extend_bounds(min, max, cells_min, cells_max),
+                None => (cells_min, cells_max),
+            });
+        }
+
+        bounds
+    }
+
+    fn region(&self, min: &Position, width: usize, height: usize) -> Array2<u8> {
+        let mut region = Array2::zeros((height, width));
+        let mut copy = |coord: &ChunkCoord, chunk: &Chunk| {
+            for (index, value) in chunk.cells.iter().enumerate() {
+                let pos = chunk_to_position(coord, &(index % CHUNK_LENGTH, index / CHUNK_LENGTH));
+                let (column, row) = (pos.x.wrapping_sub(min.x) as usize, pos.y.wrapping_sub(min.y) as usize);
+
+                if *value != 0 && column < width && row < height {
+                    region[[row, column]] = *value;
+                }
+            }
+        };
+
+        let columns = chunks_along(min.x, width);
+        let rows = chunks_along(min.y, height);
+
+        // look up the chunks under the region, unless there are fewer chunks
+        // in the whole grid than that
+        if columns.len().saturating_mul(rows.len()) <= self.chunks.len() {
+            for y in rows {
+                for x in columns.clone() {
+                    if let Some(index) = self.indices.get(&(x, y)) {
+                        let (coord, chunk) = &self.chunks[*index];
+                        copy(coord, chunk);
+                    }
+                }
+            }
         } else {
-            0
+            for (coord, chunk) in &self.chunks {
+                let chunk_min = chunk_to_position(coord, &(0, 0));
+
+                if chunk_overlaps(chunk_min.x, min.x, width) && chunk_overlaps(chunk_min.y, min.y, height) {
+                    copy(coord, chunk);
+                }
+            }
         }
+
+        region
     }
 
-    fn set(&mut self, pos: &Position, value: u8) -> u8 {
-        let (chunk_coords, chunk_offset) = position_to_chunk(pos);
+    fn set_region(&mut self, min: &Position, values: &Array2<u8>) -> Option<()> {
+        let (height, width) = values.dim();
 
-        let chunk = self.get_chunk_mut(&chunk_coords);
-        let old_value = chunk.set(&chunk_offset, value);
+        for y in chunks_along(min.y, height) {
+            for x in chunks_along(min.x, width) {
+                let coord = (x, y);
+                let mut index = self.indices.get(&coord).copied();
 
-        // remove the chunk if it is now empty
-        if chunk.is_empty() {
-            self.chunks.remove(&chunk_coords);
+                for cell in 0..CHUNK_LENGTH * CHUNK_LENGTH {
+                    let offset = (cell % CHUNK_LENGTH, cell / CHUNK_LENGTH);
+                    let pos = chunk_to_position(&coord, &offset);
+                    let (column, row) = (pos.x.wrapping_sub(min.x) as usize, pos.y.wrapping_sub(min.y) as usize);
+
+                    if column >= width || row >= height {
+                        continue;
+                    }
+
+                    let value = values[[row, column]];
+
+                    // only make a chunk for a non-zero cell
+                    let index = match index {
+                        Some(index) => index,
+                        None if value == 0 => continue,
+                        None => *index.insert(self.insert_chunk(&coord)),
+                    };
+
+                    let old_value = self.chunks[index].1.set(&offset, value);
+                    self.count_cell(old_value, value);
+                }
+
+                if let Some(index) = index {
+                    if self.chunks[index].1.is_empty() {
+                        self.remove_chunk(index);
+                    }
+                }
+            }
+        }
+
+        Some(())
+    }
+
+    fn clear(&mut self) {
+        self.chunks.clear();
+        self.indices.clear();
+        self.cell_count = 0;
+    }
+}
+
+/// Builds the hashers of the chunk coordinates of one [`HashGrid`], with
+/// keys which are drawn for each grid like those of [`RandomState`]. Programs
+/// can not know the keys, so they can not choose chunk coordinates which
+/// collide on purpose.
+#[derive(Clone)]
+struct ChunkHashState {
+    keys: (u64, u64),
+}
+
+impl Default for ChunkHashState {
+    fn default() -> Self {
+        let random = RandomState::new();
+
+        Self {
+            keys: (random.hash_one(0u8), random.hash_one(1u8)),
+        }
+    }
+}
+
+impl BuildHasher for ChunkHashState {
+    type Hasher = ChunkHasher;
+
+    fn build_hasher(&self) -> ChunkHasher {
+        ChunkHasher {
+            hash: self.keys.0,
+            key: self.keys.1,
+        }
+    }
+}
+
+/// A hasher for chunk coordinates, which is much faster than the default
+/// one. Each word is multiplied by the key into 128 bits, whose halves are
+/// folded together, so that every bit of the word reaches the low bits which
+/// pick a bucket.
+struct ChunkHasher {
+    hash: u64,
+    key: u64,
+}
+
+impl ChunkHasher {
+    fn add(&mut self, word: u64) {
+        let product = (self.hash ^ word) as u128 * self.key as u128;
+        self.hash = product as u64 ^ (product >> 64) as u64;
+    }
+}
+
+impl Hasher for ChunkHasher {
+    fn finish(&self) -> u64 {
+        self.hash
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.add(byte as u64);
+        }
+    }
+
+    fn write_isize(&mut self, i: isize) {
+        self.add(i as u64);
+    }
+}
+
+/// A grid stored in a single array, which grows to fit the cells that are
+/// written.
+///
+/// Faster than a [`HashGrid`] for programs which stay within a small
+/// rectangle, but every cell between the furthest written ones is kept, so
+/// programs which write far apart use a lot of memory. To bound it, the
+/// grid never grows past a largest area, and writes which would need more
+/// fail with [`Grid::try_set`].
+#[derive(Clone)]
+pub struct DenseGrid {
+    /// The cells, by row and then column.
+    cells: Array2<u8>,
+    /// The position of the first cell.
+    origin: Position,
+    cell_count: usize,
+    /// The largest number of cells, zero or not, which may be kept.
+    max_area: usize,
+}
+
+impl DenseGrid {
+    /// The largest area of the default grid, which takes 256 MiB.
+    pub const DEFAULT_MAX_AREA: usize = 1 << 28;
+
+    /// A grid which keeps at most `max_area` cells, counting the zero cells
+    /// between the others.
+    pub fn with_max_area(max_area: usize) -> Self {
+        Self {
+            cells: Array2::zeros((0, 0)),
+            origin: Position::default(),
+            cell_count: 0,
+            max_area,
+        }
+    }
+
+    /// The index of `pos` in the cells, if it is within the bounds.
+    fn index(&self, pos: &Position) -> Option<[usize; 2]> {
+        let (rows, columns) = self.cells.dim();
+        let x = usize::try_from(pos.x.checked_sub(self.origin.x)?).ok()?;
+        let y = usize::try_from(pos.y.checked_sub(self.origin.y)?).ok()?;
+
+        if x < columns && y < rows {
+            Some([y, x])
+        } else {
+            None
+        }
+    }
+
+    /// Grow the bounds to include `pos`, copying the cells over. Returns
+    /// `false`, changing nothing, if the grid would be larger than its
+    /// largest area.
+    fn grow_to(&mut self, pos: &Position) -> bool {
+        let (rows, columns) = self.cells.dim();
+        let grown = grow_axis(self.origin.x, columns, pos.x).zip(grow_axis(self.origin.y, rows, pos.y));
+
+        let ((x, new_columns), (y, new_rows)) = match grown {
+            Some(grown) => grown,
+            None => return false,
+        };
+
+        if new_columns.checked_mul(new_rows).is_none_or(|area| area > self.max_area) {
+            return false;
+        }
+
+        let mut cells = Array2::zeros((new_rows, new_columns));
+
+        if !self.cells.is_empty() {
+            // where the old cells start among the new ones
+            let column = (self.origin.x as i128 - x as i128) as usize;
+            let row = (self.origin.y as i128 - y as i128) as usize;
+
+            cells.slice_mut(s![row..row + rows, column..column + columns]).assign(&self.cells);
+        }
+
+        self.cells = cells;
+        self.origin = Position::new(x, y);
+        true
+    }
+}
+
+impl Default for DenseGrid {
+    fn default() -> Self {
+        Self::with_max_area(Self::DEFAULT_MAX_AREA)
+    }
+}
+
+impl Grid for DenseGrid {
+    fn get(&self, pos: &Position) -> u8 {
+        match self.index(pos) {
+            Some(index) => self.cells[index],
+            None => 0,
+        }
+    }
+
+    fn try_set(&mut self, pos: &Position, value: u8) -> Option<u8> {
+        let index = match self.index(pos) {
+            Some(index) => index,
+            // zero is already there, no need to grow for it
+            None if value == 0 => return Some(0),
+            None if self.grow_to(pos) => self.index(pos).expect("grid was grown to include the position"),
+            None => return None,
+        };
+
+        let old_value = std::mem::replace(&mut self.cells[index], value);
+
+        if old_value == 0 && value != 0 {
+            self.cell_count += 1;
+        } else if old_value != 0 && value == 0 {
+            self.cell_count -= 1;
+        }
+
+        Some(old_value)
+    }
+
+    fn cell_count(&self) -> usize {
+        self.cell_count
+    }
+
+    fn cells(&self) -> Box<dyn Iterator<Item = (Position, u8)> + '_> {
+        Box::new(self.cells.indexed_iter()
+            .filter(|(_, value)| **value != 0)
+            .map(move |((y, x), value)| {
+                (Position::new(self.origin.x + x as isize, self.origin.y + y as isize), *value)
+            }))
+    }
+
+    fn clear(&mut self) {
+        // shrink back down as well
+        *self = Self::with_max_area(self.max_area);
+    }
+}
+
+/// A grid of a fixed size, which wraps around at its edges like the
+/// playfield of Befunge. Moving off one edge leads back in at the other.
+#[derive(Clone)]
+pub struct TorusGrid {
+    /// The cells, by row and then column.
+    cells: Array2<u8>,
+    cell_count: usize,
+}
+
+impl TorusGrid {
+    /// The width of the default grid, which is as large as the playfield of
+    /// Befunge-93.
+    pub const DEFAULT_WIDTH: usize = 80;
+    /// The height of the default grid.
+    pub const DEFAULT_HEIGHT: usize = 25;
+
+    /// Panics if `width` or `height` is zero, or either does not fit in an
+    /// `isize`.
+    pub fn new(width: usize, height: usize) -> Self {
+        assert!(width > 0 && height > 0, "torus grid must not be empty");
+        assert!(isize::try_from(width).is_ok() && isize::try_from(height).is_ok(), "torus grid is too large");
+
+        Self {
+            cells: Array2::zeros((height, width)),
+            cell_count: 0,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.cells.ncols()
+    }
+
+    pub fn height(&self) -> usize {
+        self.cells.nrows()
+    }
+
+    /// Set the cell which `pos` refers to to `value`, returning its old
+    /// value.
+    pub fn set(&mut self, pos: &Position, value: u8) -> u8 {
+        let index = self.index(pos);
+        let old_value = std::mem::replace(&mut self.cells[index], value);
+
+        if old_value == 0 && value != 0 {
+            self.cell_count += 1;
+        } else if old_value != 0 && value == 0 {
+            self.cell_count -= 1;
         }
 
         old_value
     }
+
+    fn index(&self, pos: &Position) -> [usize; 2] {
+        let pos = self.wrap(*pos);
+        [pos.y as usize, pos.x as usize]
+    }
 }
 
+impl Default for TorusGrid {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_WIDTH, Self::DEFAULT_HEIGHT)
+    }
+}
+
+impl Grid for TorusGrid {
+    fn get(&self, pos: &Position) -> u8 {
+        self.cells[self.index(pos)]
+    }
+
+    fn try_set(&mut self, pos: &Position, value: u8) -> Option<u8> {
+        Some(self.set(pos, value))
+    }
+
+    fn cell_count(&self) -> usize {
+        self.cell_count
+    }
+
+    fn cells(&self) -> Box<dyn Iterator<Item = (Position, u8)> + '_> {
+        Box::new(self.cells.indexed_iter()
+            .filter(|(_, value)| **value != 0)
+            .map(|((y, x), value)| (Position::new(x as isize, y as isize), *value)))
+    }
+
+    fn clear(&mut self) {
+        self.cells.fill(0);
+        self.cell_count = 0;
+    }
+
+    fn wrap(&self, pos: Position) -> Position {
+        Position::new(
+            pos.x.rem_euclid(self.width() as isize),
+            pos.y.rem_euclid(self.height() as isize),
+        )
+    }
+
+    fn lap(&self, direction: Direction) -> Option<usize> {
+        Some(match direction {
+            Direction::Right | Direction::Left => self.width(),
+            Direction::Up | Direction::Down => self.height(),
+        })
+    }
+}
+
+/// The start and length of an axis of a [`DenseGrid`] which spans `length`
+/// cells from `start`, grown to include `coord`, or `None` if the length
+/// does not fit in a `usize`.
+///
+/// A side which grows does so by at least as many cells as the axis already
+/// has, so that writing along a line does not copy the grid every time.
+fn grow_axis(start: isize, length: usize, coord: isize) -> Option<(isize, usize)> {
+    if length == 0 {
+        return Some((coord, 1));
+    }
+
+    // the ends of the axis may not fit in an isize while growing
+    let margin = length.max(CHUNK_LENGTH) as i128;
+    let coord = coord as i128;
+    let mut start = start as i128;
+    let mut end = start + length as i128;
+
+    if coord < start {
+        start = coord.min(start - margin).max(isize::MIN as i128);
+    }
+
+    if coord >= end {
+        end = (coord + 1).max(end + margin).min(isize::MAX as i128 + 1);
+    }
+
+    let length = usize::try_from(end - start).ok()?;
+    Some((start as isize, length))
+}
+
+#[derive(Clone)]
 struct Chunk {
-    cells: Array2<u8>,
+    /// The cells, by row and then column.
+    cells: [u8; CHUNK_LENGTH * CHUNK_LENGTH],
     non_zero_count: usize,
 }
 
@@ -67,14 +651,13 @@ impl Chunk {
     pub fn get(&self, offset: &ChunkOffset) -> u8 {
         assert!(offset.0 < CHUNK_LENGTH && offset.1 < CHUNK_LENGTH);
 
-        self.cells[[offset.1, offset.0]]
+        self.cells[offset.1 * CHUNK_LENGTH + offset.0]
     }
 
     pub fn set(&mut self, offset: &ChunkOffset, value: u8) -> u8 {
         assert!(offset.0 < CHUNK_LENGTH && offset.1 < CHUNK_LENGTH);
 
-        let old_value = self.cells[[offset.1, offset.0]];
-        self.cells[[offset.1, offset.0]] = value;
+        let old_value = std::mem::replace(&mut self.cells[offset.1 * CHUNK_LENGTH + offset.0], value);
 
         if old_value == 0 && value != 0 {
             // changed to non-zero, increment count
@@ -90,31 +673,81 @@ impl Chunk {
     pub fn is_empty(&self) -> bool {
         self.non_zero_count == 0
     }
+
+    /// The smallest and the largest offset of the rectangle which contains
+    /// every non-zero cell. The chunk must not be empty.
+    pub fn bounding_box(&self) -> (ChunkOffset, ChunkOffset) {
+        let mut min = (CHUNK_LENGTH, CHUNK_LENGTH);
+        let mut max = (0, 0);
+
+        for (index, value) in self.cells.iter().enumerate() {
+            if *value != 0 {
+                let (x, y) = (index % CHUNK_LENGTH, index / CHUNK_LENGTH);
+                min = (min.0.min(x), min.1.min(y));
+                max = (max.0.max(x), max.1.max(y));
+            }
+        }
+
+        (min, max)
+    }
 }
 
 impl Default for Chunk {
     fn default() -> Self {
         Self {
-            cells: Array2::zeros((CHUNK_LENGTH, CHUNK_LENGTH)),
+            cells: [0; CHUNK_LENGTH * CHUNK_LENGTH],
             non_zero_count: 0,
         }
     }
 }
 
 fn position_to_chunk(pos: &Position) -> (ChunkCoord, ChunkOffset) {
-    let mut coord = (pos.x / CHUNK_LENGTH as isize, pos.y / CHUNK_LENGTH as isize);
+    let length = CHUNK_LENGTH as isize;
 
-    // offset negative chunk coords
+    // round towards negative infinity, so that the offset is never negative
+    let coord = (pos.x.div_euclid(length), pos.y.div_euclid(length));
+    let offset = (pos.x.rem_euclid(length) as usize, pos.y.rem_euclid(length) as usize);
 
-    if pos.x < 0 {
-        coord.0 -= 1;
-    }
+    (coord, offset)
+}
 
-    if pos.y < 0 {
-        coord.1 -= 1;
-    }
+fn chunk_to_position(coord: &ChunkCoord, offset: &ChunkOffset) -> Position {
+    Position::new(
+        coord.0 * CHUNK_LENGTH as isize + offset.0 as isize,
+        coord.1 * CHUNK_LENGTH as isize + offset.1 as isize,
+    )
+}
 
-    let offset = ((pos.x - (CHUNK_LENGTH as isize) * coord.0).abs() as usize, pos.y.rem_euclid(CHUNK_LENGTH as isize) as usize);
+/// The coordinates of the chunks which hold any of the `length` cells from
+/// `min` along an axis, wrapping around like those of a [`Position`].
+fn chunks_along(min: isize, length: usize) -> impl ExactSizeIterator<Item = isize> + Clone {
+    let offset = min.rem_euclid(CHUNK_LENGTH as isize) as usize;
+    let start = min.wrapping_sub(offset as isize);
+    let count = match length {
+        0 => 0,
+        _ => offset.saturating_add(length).div_ceil(CHUNK_LENGTH),
+    };
 
-    (coord, offset)
+    (0..count).map(move |index| {
+        start.wrapping_add(index.wrapping_mul(CHUNK_LENGTH) as isize).div_euclid(CHUNK_LENGTH as isize)
+    })
+}
+
+/// Whether any of the cells of a chunk from `start` along an axis are among
+/// the `length` cells from `min`, with the coordinates wrapping around like
+/// those of a [`Position`].
+fn chunk_overlaps(start: isize, min: isize, length: usize) -> bool {
+    let offset = start.wrapping_sub(min) as usize;
+
+    // the chunk either starts within the range, or wraps around into it
+    offset < length || offset.wrapping_add(CHUNK_LENGTH - 1) < offset
+}
+
+/// The bounds from `min` to `max`, grown to include those from `other_min`
+/// to `other_max`.
+fn extend_bounds(min: Position, max: Position, other_min: Position, other_max: Position) -> (Position, Position) {
+    (
+        Position::new(min.x.min(other_min.x), min.y.min(other_min.y)),
+        Position::new(max.x.max(other_max.x), max.y.max(other_max.y)),
+    )
 }
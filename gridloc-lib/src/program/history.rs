@@ -0,0 +1,156 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::mem;
+use std::rc::Rc;
+
+use crate::*;
+
+/// A pointer and the tape evaluated with it, which `@` removed.
+pub(super) type Killed<'a> = (Rc<RefCell<Pointer>>, Box<dyn EvalTape + 'a>);
+
+/// The most recent steps of a program, kept so that they can be undone.
+pub struct History<'a> {
+    records: VecDeque<Undo<'a>>,
+    limit: usize,
+}
+
+/// Everything needed to undo a single step. Grid writes and saved positions
+/// are undone from the events of the step, which include the old values.
+pub(super) struct Undo<'a> {
+    tape_count: usize,
+    tape_location: Option<TapeLocation>,
+    pointer: Option<Pointer>,
+    string_mode: Option<StringModeKind>,
+    steps: u64,
+    output_bytes: usize,
+    events: Vec<Event>,
+    killed: Option<Killed<'a>>,
+    input: Vec<u8>,
+}
+
+impl<'a, G: 'a + Grid, Rng: rand::Rng> ProgramState<'a, G, Rng> {
+    /// Keep enough information to undo up to `limit` of the most recent
+    /// steps with [`step_back`](Self::step_back).
+    pub fn record_history(&mut self, limit: usize) {
+        self.history = Some(History {
+            records: VecDeque::new(),
+            limit,
+        });
+    }
+
+    /// Forget every step which could be undone, as undoing them would not
+    /// bring back the states of the program once it was changed from
+    /// outside of a step.
+    pub fn clear_history(&mut self) {
+        if let Some(history) = &mut self.history {
+            history.records.clear();
+        }
+    }
+
+    /// The number of steps which can currently be undone.
+    pub fn history_len(&self) -> usize {
+        self.history.as_ref()
+            .map_or(0, |history| history.records.len())
+    }
+
+    /// Undo the most recent step, returning the input which it consumed so
+    /// that it can be read again. Output which the step wrote can not be
+    /// taken back, and `?` draws new random values when it is run again.
+    ///
+    /// Returns `None` if there is no step in the history.
+    pub fn step_back(&mut self) -> Option<Vec<u8>> {
+        let undo = self.history.as_mut()?.records.pop_back()?;
+
+        // undo any change to the pointer and tape stacks first, so that the
+        // pointer and tape on top are the ones which the step started with
+        if let Some((pointer, tape)) = undo.killed {
+            self.pointers.push(pointer);
+            self.eval_tapes.push(tape);
+        } else if self.eval_tapes.len() > undo.tape_count {
+            self.pointers.pop();
+            self.eval_tapes.pop();
+        }
+
+        for event in undo.events.iter().rev() {
+            match *event {
+                Event::CellWritten { position, old, .. } => {
+                    // the grid holds the cell already, as it was written
+                    let _ = self.grid.borrow_mut().try_set(&position, old);
+                }
+                Event::PositionSaved { slot, old: Some(old), .. } => {
+                    self.saved_positions.insert(slot, old);
+                }
+                Event::PositionSaved { slot, old: None, .. } => {
+                    self.saved_positions.remove(&slot);
+                }
+                Event::CellRead { .. } => {}
+            }
+        }
+
+        if let (Some(pointer), Some(current)) = (undo.pointer, self.current_pointer()) {
+            *current.borrow_mut() = pointer;
+        }
+
+        if let (Some(location), Some(tape)) = (undo.tape_location, self.eval_tapes.last_mut()) {
+            tape.seek(location);
+        }
+
+        self.string_mode = undo.string_mode;
+        self.steps = undo.steps;
+        self.output_bytes = undo.output_bytes;
+        self.events.clear();
+
+        Some(undo.input)
+    }
+
+    /// How many steps back the most recent write to `position` happened,
+    /// counting the writing step itself. Returns `None` if no step in the
+    /// history wrote to it.
+    pub fn steps_since_write(&self, position: &Position) -> Option<usize> {
+        let history = self.history.as_ref()?;
+
+        history.records.iter()
+            .rev()
+            .position(|undo| undo.events.iter().any(|event| {
+                matches!(event, Event::CellWritten { position: written, .. } if written == position)
+            }))
+            .map(|index| index + 1)
+    }
+
+    /// Capture what is needed to undo the step which is about to run.
+    pub(super) fn begin_undo(&self) -> Undo<'a> {
+        Undo {
+            tape_count: self.eval_tapes.len(),
+            tape_location: self.current_tape().map(|tape| tape.location()),
+            pointer: self.current_pointer().map(|pointer| pointer.borrow().clone()),
+            string_mode: self.string_mode,
+            steps: self.steps,
+            output_bytes: self.output_bytes,
+            events: Vec::new(),
+            killed: None,
+            input: Vec::new(),
+        }
+    }
+
+    /// Add the step which just ran to the history, unless it did not read a
+    /// value and so changed nothing.
+    pub(super) fn finish_undo(&mut self, mut undo: Undo<'a>) {
+        let killed = self.killed.take();
+
+        if self.steps == undo.steps {
+            return;
+        }
+
+        undo.events = self.events.clone();
+        undo.killed = killed;
+        undo.input = mem::take(&mut self.consumed_input);
+
+        if let Some(history) = &mut self.history {
+            history.records.push_back(undo);
+
+            if history.records.len() > history.limit {
+                history.records.pop_front();
+            }
+        }
+    }
+}
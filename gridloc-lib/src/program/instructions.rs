@@ -0,0 +1,269 @@
+use std::collections::BTreeMap;
+use std::io::{BufRead, Write};
+
+use crate::*;
+
+/// Everything besides the pointer which the instructions of a single
+/// pointer act on. Both [`ProgramState`] and [`NativeState`] run those
+/// instructions through here, so that they always agree.
+pub(crate) struct PointerContext<'c, G, Rng: ?Sized> {
+    pub grid: &'c mut G,
+    pub saved_positions: &'c mut BTreeMap<u8, Position>,
+    pub rng: &'c mut Rng,
+    pub limits: &'c Limits,
+    /// Where the instruction is, for errors.
+    pub location: Location,
+    pub output_bytes: &'c mut usize,
+    /// What the instruction did, if anyone is watching.
+    pub events: Option<&'c mut Vec<Event>>,
+    /// The input which the instruction read, if it may be undone.
+    pub consumed_input: Option<&'c mut Vec<u8>>,
+}
+
+impl<G: Grid, Rng: rand::Rng + ?Sized> PointerContext<'_, G, Rng> {
+    /// Run `instruction` with `pointer`. Instructions which act on the
+    /// program as a whole (loop brackets, quotes, `e` and `@`) fail with
+    /// [`Error::Unsupported`], as only the program knows how to run them.
+    pub fn execute<R: BufRead, W: Write>(&mut self, instruction: Instruction, pointer: &mut Pointer, reader: &mut R, writer: &mut W) -> Result<(), Error> {
+        use Instruction::*;
+
+        let p_value = pointer.value;
+        let g_value = if instruction.reads_cell() {
+            self.read_cell(&pointer.position)
+        } else {
+            0
+        };
+
+        match instruction {
+            SetDirection(direction) => {
+                pointer.direction = direction;
+            }
+            MoveOne => {
+                pointer.move_pointer(1);
+            }
+            MoveMultiple => {
+                pointer.move_pointer(p_value);
+            }
+            ReadValue => {
+                // read grid value to pointer
+                pointer.value = g_value;
+            }
+            WriteValue => {
+                // write pointer value to grid
+                self.write_cell(&pointer.position, pointer.value)?;
+            }
+            WriteValueMove => {
+                // write pointer value to grid
+                self.write_cell(&pointer.position, pointer.value)?;
+
+                // move pointer forward
+                pointer.move_pointer(1);
+            }
+            SwapValue => {
+                // set grid value
+                self.write_cell(&pointer.position, pointer.value)?;
+
+                // set pointer value
+                pointer.value = g_value;
+            }
+            PushPosition => {
+                let length = pointer.position_stack.len() + 1;
+                self.ensure_within(Limit::PositionStack, length, self.limits.max_position_stack)?;
+
+                let pos = pointer.position;
+                pointer.position_stack.push(pos);
+            }
+            PopPosition => {
+                // only perform if the stack is not empty
+                if let Some(pos) = pointer.position_stack.pop() {
+                    pointer.position = pos;
+                }
+            }
+            SwapPosition => {
+                // only perform if the stack is not empty
+                if let Some(new_pos) = pointer.position_stack.pop() {
+                    // push the current position
+                    let current_pos = pointer.position;
+                    pointer.position_stack.push(current_pos);
+
+                    // go to the position that was at the top
+                    pointer.position = new_pos;
+                }
+            }
+            SavePosition => {
+                let old = self.saved_positions.insert(pointer.value, pointer.position);
+
+                self.record(Event::PositionSaved {
+                    slot: pointer.value,
+                    old,
+                    new: pointer.position,
+                });
+            }
+            LoadPosition => {
+                if let Some(pos) = self.saved_positions.get(&pointer.value) {
+                    pointer.position = *pos;
+                }
+            }
+            Value(value) => {
+                pointer.value &= 0b1111; // keep last four bits
+                pointer.value <<= 4; // move those four to the left
+                pointer.value += value & 0b1111; // put in the new value
+            }
+            Add => {
+                pointer.value = p_value.wrapping_add(g_value);
+            }
+            Subtract => {
+                pointer.value = p_value.wrapping_sub(g_value);
+            }
+            Multiply => {
+                pointer.value = p_value.wrapping_mul(g_value);
+            }
+            Divide => {
+                if g_value == 0 {
+                    return Err(Error::Arithmetic {
+                        fault: ArithmeticFault::DivideByZero,
+                        location: self.location,
+                    });
+                }
+
+                pointer.value = p_value.wrapping_div(g_value);
+            }
+            Modulo => {
+                if g_value == 0 {
+                    return Err(Error::Arithmetic {
+                        fault: ArithmeticFault::ModuloByZero,
+                        location: self.location,
+                    });
+                }
+
+                pointer.value = p_value.wrapping_rem(g_value);
+            }
+            Equals => {
+                pointer.value = (p_value == g_value) as u8;
+            }
+            GreaterThan => {
+                pointer.value = (p_value > g_value) as u8;
+            }
+            LogicalAnd => {
+                pointer.value = (p_value != 0 && g_value != 0) as u8;
+            }
+            LogicalOr => {
+                pointer.value = (p_value != 0 || g_value != 0) as u8;
+            }
+            LogicalNot => {
+                pointer.value = (p_value == 0) as u8;
+            }
+            Random => {
+                pointer.value = self.rng.gen();
+            }
+            Write { kind } => {
+                // bytes are counted as they are produced, so that the limit
+                // also bounds how long a string grows
+                let value = match kind {
+                    IOKind::Character => {
+                        let value = (g_value as char).to_string();
+                        self.count_output(value.len())?;
+                        value
+                    }
+                    IOKind::Number => {
+                        let value = format!("{}", g_value);
+                        self.count_output(value.len())?;
+                        value
+                    }
+                    IOKind::String => {
+                        let mut s = String::new();
+                        let mut current_value = g_value;
+
+                        // a line around a grid which wraps may have no zero
+                        // to end it, so stop once the pointer is back
+                        let lap = self.grid.lap(pointer.direction).unwrap_or(usize::MAX);
+                        let mut length = 0;
+
+                        while current_value != 0 && length < lap {
+                            // push the character value to the string
+                            let c = current_value as char;
+                            self.count_output(c.len_utf8())?;
+                            s.push(c);
+                            length += 1;
+
+                            // move pointer
+                            pointer.move_pointer(1);
+                            current_value = self.read_cell(&pointer.position);
+                        }
+
+                        s
+                    },
+                };
+
+                write!(writer, "{}", value)?;
+                writer.flush()?;
+            }
+            Read { kind } => {
+                let mut buffer = String::new();
+                reader.read_line(&mut buffer)?;
+
+                if let Some(consumed_input) = &mut self.consumed_input {
+                    consumed_input.extend_from_slice(buffer.as_bytes());
+                }
+
+                buffer = buffer.trim().to_string();
+
+                let values = match kind {
+                    IOKind::Character => {
+                        let c = buffer.chars().next().unwrap_or(0 as char);
+                        vec![c as u8]
+                    }
+                    IOKind::Number => {
+                        let num = buffer.parse::<usize>().unwrap_or(0) as u8;
+                        vec![num]
+                    }
+                    IOKind::String => {
+                        buffer.chars().map(|c| c as u8).collect()
+                    }
+                };
+
+                for value in values {
+                    self.write_cell(&pointer.position, value)?;
+                    pointer.move_pointer(1);
+                }
+            }
+            StartLoop | EndLoop | ToggleStringMode { .. } | Evaluate | Kill => {
+                return Err(Error::Unsupported { instruction, location: self.location });
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn read_cell(&mut self, position: &Position) -> u8 {
+        let value = self.grid.get(position);
+        self.record(Event::CellRead { position: self.grid.wrap(*position), value });
+
+        value
+    }
+
+    pub fn write_cell(&mut self, position: &Position, value: u8) -> Result<(), Error> {
+        let position = self.grid.wrap(*position);
+        let old = self.grid.try_set(&position, value)
+            .ok_or(Error::ResourceLimit { limit: Limit::GridSize, location: self.location })?;
+        self.record(Event::CellWritten { position, old, new: value });
+
+        self.ensure_within(Limit::Cells, self.grid.cell_count(), self.limits.max_cells)
+    }
+
+    /// Count `bytes` more of output, failing once there is too much.
+    fn count_output(&mut self, bytes: usize) -> Result<(), Error> {
+        *self.output_bytes += bytes;
+        self.ensure_within(Limit::Output, *self.output_bytes, self.limits.max_output)
+    }
+
+    fn record(&mut self, event: Event) {
+        if let Some(events) = &mut self.events {
+            events.push(event);
+        }
+    }
+
+    fn ensure_within<T: PartialOrd>(&self, limit: Limit, used: T, max: Option<T>) -> Result<(), Error> {
+        ensure_within(limit, used, max, self.location)
+    }
+}
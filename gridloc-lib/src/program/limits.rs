@@ -0,0 +1,57 @@
+use std::fmt;
+
+use crate::{Error, Location};
+
+/// Bounds on the resources which a program may use. Exceeding any of them
+/// stops the program with [`Error::ResourceLimit`](crate::Error).
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Limits {
+    /// Maximum number of steps to run.
+    pub max_steps: Option<u64>,
+    /// Maximum number of non-zero grid cells. This also bounds the number
+    /// of chunks which a [`HashGrid`](crate::HashGrid) keeps alive.
+    pub max_cells: Option<usize>,
+    /// Maximum depth of the eval tape stack, where the source code is at
+    /// depth 0.
+    pub max_tape_depth: Option<usize>,
+    /// Maximum length of the position stack of any pointer.
+    pub max_position_stack: Option<usize>,
+    /// Maximum number of bytes of output.
+    pub max_output: Option<usize>,
+}
+
+/// One of the bounds in [`Limits`], or the largest size of the grid itself.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Limit {
+    Steps,
+    Cells,
+    TapeDepth,
+    PositionStack,
+    Output,
+    /// A write would have grown the grid past the largest size it may
+    /// have, such as the largest area of a [`DenseGrid`](crate::DenseGrid).
+    GridSize,
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Limit::Steps => "step",
+            Limit::Cells => "grid cell",
+            Limit::TapeDepth => "eval tape depth",
+            Limit::PositionStack => "position stack",
+            Limit::Output => "output",
+            Limit::GridSize => "grid size",
+        };
+
+        write!(f, "{} limit", name)
+    }
+}
+
+/// Fail with `limit` at `location` if `used` is more than `max`.
+pub(crate) fn ensure_within<T: PartialOrd>(limit: Limit, used: T, max: Option<T>, location: Location) -> Result<(), Error> {
+    match max {
+        Some(max) if used > max => Err(Error::ResourceLimit { limit, location }),
+        _ => Ok(()),
+    }
+}
@@ -1,22 +1,59 @@
 use std::cell::RefCell;
 use std::collections::BTreeMap;
-use std::io;
+use std::fmt;
 use std::io::{BufRead, Write};
 use std::ops::{Add, Sub, Mul};
 use std::rc::Rc;
 
+use serde::{Deserialize, Serialize};
+
+use crate::{Error, Location, TapeFault};
+
+mod bytecode;
+pub use bytecode::*;
+
+mod event;
+pub use event::*;
+
 mod grid;
 pub use grid::*;
 
+mod history;
+use history::{History, Killed};
+
+mod instructions;
+pub(crate) use instructions::PointerContext;
+
+mod limits;
+pub use limits::*;
+
 mod parser;
 pub use parser::*;
 
+mod snapshot;
+pub use snapshot::*;
+
+mod syntax;
+pub use syntax::*;
+
+mod trace;
+pub use trace::*;
+
 pub struct ProgramState<'a, G: Grid, Rng: rand::Rng> {
     pub eval_tapes: Vec<Box<dyn EvalTape + 'a>>,
     pub grid: Rc<RefCell<G>>,
     pub pointers: Vec<Rc<RefCell<Pointer>>>,
     pub saved_positions: BTreeMap<u8, Position>,
     pub string_mode: Option<StringModeKind>,
+    pub limits: Limits,
+    events: Vec<Event>,
+    location: Location,
+    steps: u64,
+    pub(crate) output_bytes: usize,
+    history: Option<History<'a>>,
+    killed: Option<Killed<'a>>,
+    consumed_input: Vec<u8>,
+    tracer: Option<Box<dyn Tracer + 'a>>,
     rng: Rng,
 }
 
@@ -28,12 +65,68 @@ impl<'a, G: 'a + Grid, Rng: rand::Rng> ProgramState<'a, G, Rng> {
             pointers: vec![Rc::new(RefCell::new(Pointer::default()))],
             saved_positions: BTreeMap::new(),
             string_mode: None,
+            limits: Limits::default(),
+            events: Vec::new(),
+            location: Location {
+                depth: 0,
+                tape: TapeLocation::Source(0),
+                pointer: None,
+                source: None,
+            },
+            steps: 0,
+            output_bytes: 0,
+            history: None,
+            killed: None,
+            consumed_input: Vec::new(),
+            tracer: None,
             rng,
         }
     }
 
-    pub fn step<R: BufRead, W: Write>(&mut self, reader: &mut R, writer: &mut W) -> io::Result<bool>{
+    pub fn step<R: BufRead, W: Write>(&mut self, reader: &mut R, writer: &mut W) -> Result<bool, Error> {
+        self.events.clear();
+        self.consumed_input.clear();
+
+        let undo = self.history.as_ref().map(|_| self.begin_undo());
+        let trace = self.tracer.as_ref().and_then(|_| self.begin_trace());
+        let result = self.run_step(reader, writer);
+        self.wrap_pointers();
+
+        // a failed step is kept as well, so that it can be stepped back from
+        if let Some(undo) = undo {
+            self.finish_undo(undo);
+        }
+
+        // and traced, though its own error takes precedence
+        if let Some(trace) = trace {
+            let traced = self.finish_trace(trace);
+            return result.and_then(|more| traced.map(|_| more));
+        }
+
+        result
+    }
+
+    fn run_step<R: BufRead, W: Write>(&mut self, reader: &mut R, writer: &mut W) -> Result<bool, Error> {
+        if let Some(tape) = self.current_tape() {
+            let has_next = tape.peek_next().is_some();
+
+            self.location = Location {
+                depth: self.eval_tapes.len() - 1,
+                tape: tape.location(),
+                pointer: self.current_pointer().map(|p| p.borrow().position),
+                source: tape.source_position(tape.location()),
+            };
+
+            // check before reading, so that a snapshot taken after hitting
+            // the limit resumes with the value which was not executed
+            if has_next {
+                self.ensure_within(Limit::Steps, self.steps + 1, self.limits.max_steps)?;
+            }
+        }
+
         if let Some(value) = self.eval_tapes.last_mut().and_then(|tape| tape.next()) {
+            self.steps += 1;
+
             let character = value as char;
             let instruction = parse_instruction(character);
 
@@ -41,12 +134,10 @@ impl<'a, G: 'a + Grid, Rng: rand::Rng> ProgramState<'a, G, Rng> {
                 if instruction == Some(Instruction::ToggleStringMode { kind: current_kind }) {
                     self.string_mode = None;
                 } else {
-                    let mut pointer = self.pointers
-                        .last()
-                        .unwrap()
-                        .borrow_mut();
+                    let pointer = self.top_pointer()?;
+                    let mut pointer = pointer.borrow_mut();
 
-                    self.grid.borrow_mut().set(&pointer.position, value);
+                    self.with_context(|context| context.write_cell(&pointer.position, value))?;
                     pointer.move_pointer(1);
                 }
             } else if let Some(instruction) = instruction {
@@ -59,38 +150,80 @@ impl<'a, G: 'a + Grid, Rng: rand::Rng> ProgramState<'a, G, Rng> {
         }
     }
 
+    /// Evaluate the grid code under the current pointer as `e` does, and step
+    /// until it is killed with `@`. Returns `false` if the code ended first,
+    /// which ends the program.
+    pub fn evaluate<R: BufRead, W: Write>(&mut self, reader: &mut R, writer: &mut W) -> Result<bool, Error> {
+        let depth = self.eval_tapes.len();
+        self.execute_instruction(Instruction::Evaluate, reader, writer)?;
+
+        while self.eval_tapes.len() > depth {
+            if !self.step(reader, writer)? {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// The number of steps which have read a value from a tape.
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// The instruction which the next step will execute, if any.
+    pub fn peek_instruction(&self) -> Option<Instruction> {
+        let instruction = self.current_tape()
+            .and_then(|tape| tape.peek_next())
+            .and_then(|value| parse_instruction(value as char))?;
+
+        match self.string_mode {
+            // in string mode, only the closing quote is an instruction
+            Some(kind) if instruction != Instruction::ToggleStringMode { kind } => None,
+            _ => Some(instruction),
+        }
+    }
+
+    /// Everything which the last step did to the grid and the saved positions.
+    pub fn last_events(&self) -> &[Event] {
+        &self.events
+    }
+
     pub fn current_pointer(&self) -> Option<Rc<RefCell<Pointer>>> {
         self.pointers.last()
-            .map(|p| p.clone())
+            .cloned()
     }
 
-    pub fn current_tape(&self) -> Option<&Box<dyn EvalTape + 'a>> {
+    pub fn current_tape(&self) -> Option<&(dyn EvalTape + 'a)> {
         self.eval_tapes.last()
+            .map(|tape| tape.as_ref())
     }
 
     pub fn current_tape_mut(&mut self) -> Option<&mut Box<dyn EvalTape + 'a>> {
         self.eval_tapes.last_mut()
     }
 
-    fn execute_instruction<R: BufRead, W: Write>(&mut self, instruction: Instruction, reader: &mut R, writer: &mut W) -> io::Result<()> {
-        use Instruction::*;
+    /// The current pointer, which must exist for an instruction to execute.
+    fn top_pointer(&self) -> Result<Rc<RefCell<Pointer>>, Error> {
+        self.current_pointer()
+            .ok_or(Error::PointerStackUnderflow { location: self.location })
+    }
 
-        let (p_value, g_value) = {
-            let pointer = self.pointers.last()
-                .unwrap()
-                .borrow();
+    /// Fail with `limit` if `used` is more than `max`.
+    fn ensure_within<T: PartialOrd>(&self, limit: Limit, used: T, max: Option<T>) -> Result<(), Error> {
+        ensure_within(limit, used, max, self.location)
+    }
 
-            let p_value = pointer.value;
-            let g_value = self.grid.borrow().get(&pointer.position);
+    fn execute_instruction<R: BufRead, W: Write>(&mut self, instruction: Instruction, reader: &mut R, writer: &mut W) -> Result<(), Error> {
+        use Instruction::*;
 
-            (p_value, g_value)
-        };
+        let top_pointer = self.top_pointer()?;
 
         match instruction {
             // instructions which operate on the pointer stack
             Evaluate => {
-                // TODO
-                let top_pointer = self.pointers.last().unwrap();
+                self.ensure_within(Limit::TapeDepth, self.eval_tapes.len(), self.limits.max_tape_depth)?;
+
                 let new_pointer = Rc::new(RefCell::new(Pointer::from_other(&top_pointer.borrow())));
 
                 let tape = GridTape {
@@ -102,233 +235,177 @@ impl<'a, G: 'a + Grid, Rng: rand::Rng> ProgramState<'a, G, Rng> {
                 self.eval_tapes.push(Box::new(tape));
             }
             StartLoop => {
-                if p_value == 0 {
-                    self.jump_loop_forwards();
+                if top_pointer.borrow().value == 0 {
+                    self.jump_loop_forwards()?;
                 }
             }
             EndLoop => {
-                if p_value != 0 {
-                    self.jump_loop_backwards();
+                if top_pointer.borrow().value != 0 {
+                    self.jump_loop_backwards()?;
                 }
             }
             Kill => {
-                self.pointers.pop();
-                self.eval_tapes.pop();
+                let pointer = self.pointers.pop();
+                let tape = self.eval_tapes.pop();
+
+                // keep them around in case the step is undone
+                if let (Some(pointer), Some(tape), Some(_)) = (pointer, tape, &self.history) {
+                    self.killed = Some((pointer, tape));
+                }
+            }
+            ToggleStringMode { kind } => {
+                // this will only be called when
+                // string mode is not active
+                self.string_mode = Some(kind);
             }
             // instructions which operate on the pointer
             _ => {
-                let mut pointer = self.pointers
-                    .last()
-                    .unwrap()
-                    .borrow_mut();
-
-                match instruction {
-                    SetDirection(direction) => {
-                        pointer.direction = direction;
-                    }
-                    MoveOne => {
-                        pointer.move_pointer(1);
-                    }
-                    MoveMultiple => {
-                        pointer.move_pointer(p_value);
-                    }
-                    ReadValue => {
-                        // read grid value to pointer
-                        pointer.value = self.grid.borrow().get(&pointer.position);
-                    }
-                    WriteValue => {
-                        // write pointer value to grid
-                        self.grid.borrow_mut().set(&pointer.position, pointer.value);
-                    }
-                    WriteValueMove => {
-                        // write pointer value to grid
-                        self.grid.borrow_mut().set(&pointer.position, pointer.value);
+                let mut pointer = top_pointer.borrow_mut();
+                self.with_context(|context| context.execute(instruction, &mut pointer, reader, writer))?;
+            }
+        }
 
-                        // move pointer forward
-                        pointer.move_pointer(1);
-                    }
-                    SwapValue => {
-                        let grid = &mut self.grid.borrow_mut();
+        Ok(())
+    }
 
-                        // temporary
-                        let grid_value = grid.get(&pointer.position);
+    /// Run `f` on what the instructions of a single pointer act on.
+    fn with_context<T>(&mut self, f: impl FnOnce(&mut PointerContext<G, Rng>) -> T) -> T {
+        let mut grid = self.grid.borrow_mut();
+
+        f(&mut PointerContext {
+            grid: &mut grid,
+            saved_positions: &mut self.saved_positions,
+            rng: &mut self.rng,
+            limits: &self.limits,
+            location: self.location,
+            output_bytes: &mut self.output_bytes,
+            events: Some(&mut self.events),
+            consumed_input: Some(&mut self.consumed_input),
+        })
+    }
 
-                        // set grid value
-                        grid.set(&pointer.position, pointer.value);
+    /// Bring the pointers which moved off a grid that wraps around back
+    /// onto it.
+    fn wrap_pointers(&mut self) {
+        let grid = self.grid.borrow();
 
-                        // set pointer value
-                        pointer.value = grid_value;
-                    }
-                    PushPosition => {
-                        let pos = pointer.position;
-                        pointer.position_stack.push(pos);
-                    }
-                    PopPosition => {
-                        // only perform if the stack is not empty
-                        if let Some(pos) = pointer.position_stack.pop() {
-                            pointer.position = pos;
-                        }
-                    }
-                    SwapPosition => {
-                        // only perform if the stack is not empty
-                        if let Some(new_pos) = pointer.position_stack.pop() {
-                            // push the current position
-                            let current_pos = pointer.position;
-                            pointer.position_stack.push(current_pos);
-
-                            // go to the position that was at the top
-                            pointer.position = new_pos;
-                        }
-                    }
-                    SavePosition => {
-                        self.saved_positions.insert(pointer.value, pointer.position);
-                    }
-                    LoadPosition => {
-                        if let Some(pos) = self.saved_positions.get(&pointer.value) {
-                            pointer.position = *pos;
-                        }
-                    }
-                    ToggleStringMode { kind } => {
-                        // this will only be called when
-                        // string mode is not active
-                        self.string_mode = Some(kind);
-                    }
-                    Value(value) => {
-                        pointer.value &= 0b1111; // keep last four bits
-                        pointer.value <<= 4; // move those four to the left
-                        pointer.value += value & 0b1111; // put in the new value
-                    }
-                    Add => {
-                        pointer.value = p_value.wrapping_add(g_value);
-                    }
-                    Subtract => {
-                        pointer.value = p_value.wrapping_sub(g_value);
-                    }
-                    Multiply => {
-                        pointer.value = p_value.wrapping_mul(g_value);
-                    }
-                    Divide => {
-                        if g_value != 0 {
-                            pointer.value = p_value.wrapping_div(g_value);
-                        } else {
-                            pointer.value = 0;
-                        }
-                    }
-                    Modulo => {
-                        pointer.value = p_value.wrapping_rem(g_value);
-                    }
-                    Equals => {
-                        pointer.value = (p_value == g_value) as u8;
-                    }
-                    GreaterThan => {
-                        pointer.value = (p_value > g_value) as u8;
-                    }
-                    LogicalAnd => {
-                        pointer.value = (p_value != 0 && g_value != 0) as u8;
-                    }
-                    LogicalOr => {
-                        pointer.value = (p_value != 0 || g_value != 0) as u8;
-                    }
-                    LogicalNot => {
-                        pointer.value = (p_value == 0) as u8;
-                    }
-                    Random => {
+        for pointer in &self.pointers {
+            let mut pointer = pointer.borrow_mut();
+            pointer.position = grid.wrap(pointer.position);
+        }
+    }
 
-                    }
-                    Write { kind } => {
-                        let value = match kind {
-                            IOKind::Character => (g_value as char).to_string(),
-                            IOKind::Number => format!("{}", g_value),
-                            IOKind::String => {
-                                let mut s = String::new();
-                                let mut current_value = g_value;
-
-                                while current_value != 0 {
-                                    // push the character value to the string
-                                    s.push(current_value as char);
-
-                                    // move pointer
-                                    pointer.move_pointer(1);
-                                    current_value = self.grid.borrow().get(&pointer.position);
-                                }
-
-                                s
-                            },
-                        };
-
-                        write!(writer, "{}", value)?;
-                        writer.flush()?;
-                    }
-                    Read { kind } => {
-                        let mut buffer = String::new();
-                        reader.read_line(&mut buffer)?;
-
-                        buffer = buffer.trim().to_string();
-
-                        let values = match kind {
-                            IOKind::Character => {
-                                let c = buffer.chars().next().unwrap_or(0 as char);
-                                vec![c as u8]
-                            }
-                            IOKind::Number => {
-                                let num = buffer.parse::<usize>().unwrap_or(0) as u8;
-                                vec![num]
-                            }
-                            IOKind::String => {
-                                buffer.chars().map(|c| c as u8).collect()
-                            }
-                        };
-
-                        for value in values {
-                            self.grid.borrow_mut().set(&pointer.position, value);
-                            pointer.move_pointer(1);
-                        }
-                    }
-                    _ => unreachable!()
-                }
-            }
+    fn jump_loop(&mut self, forwards: bool) -> Result<(), Error> {
+        // the loop instruction was read from this tape, so it must exist
+        let tape = self.eval_tapes.last_mut().unwrap();
+
+        if tape.jump_loop(forwards) {
+            Ok(())
+        } else {
+            Err(Error::InvalidTape {
+                fault: TapeFault::UnmatchedLoop,
+                location: self.location,
+            })
         }
+    }
 
-        Ok(())
+    fn jump_loop_forwards(&mut self) -> Result<(), Error> {
+        self.jump_loop(true)
     }
 
-    fn jump_loop(&mut self, forwards: bool) {
-        use Instruction::*;
+    fn jump_loop_backwards(&mut self) -> Result<(), Error> {
+        self.jump_loop(false)
+    }
+}
 
-        let tape = self.eval_tapes.last_mut().unwrap();
+pub trait EvalTape {
+    fn peek_next(&self) -> Option<u8>;
+    fn next(&mut self) -> Option<u8>;
+    fn prev(&mut self) -> Option<u8>;
+    fn location(&self) -> TapeLocation;
+    /// Move the tape back to `location`, as returned by [`location`](Self::location).
+    fn seek(&mut self, location: TapeLocation);
+    /// The compiled form of this tape, if it never changes while it runs.
+    fn compiled(&self) -> Option<Rc<Bytecode>> {
+        None
+    }
+
+    /// Where `location` of this tape came from in the original source code,
+    /// if it is known.
+    fn source_position(&self, _location: TapeLocation) -> Option<SourcePosition> {
+        None
+    }
 
-        let mut depth = 0;
+    /// Where every value of this tape came from in the original source
+    /// code, if it is known.
+    fn source_map(&self) -> Option<&SourceMap> {
+        None
+    }
 
-        while let Some(value) = if forwards { tape.next() } else { tape.prev() } {
-            if let Some(instr @ (StartLoop | EndLoop)) = parse_instruction(value as char) {
-                let is_matching = instr == if forwards { EndLoop } else { StartLoop };
+    /// Jump from the loop bracket which was just read to its partner, so
+    /// that the next value is the one after the `]` when jumping forwards,
+    /// or the `[` itself when jumping backwards. Returns `false` if the tape
+    /// ended before the partner was found.
+    ///
+    /// Brackets inside string literals are data, and are skipped like
+    /// [`Brackets`] does. A literal reads the same backwards, so the same
+    /// goes for scanning in either direction.
+    fn jump_loop(&mut self, forwards: bool) -> bool {
+        use Instruction::*;
 
-                if is_matching {
-                    if depth > 1 {
-                        depth -= 1;
+        // scanning backwards reads the `]` which started the jump, while
+        // scanning forwards starts after the `[`, so that one is counted
+        // up front. starting forwards at 0 would stop at the `]` of the
+        // first nested loop instead of our own
+        let mut depth = if forwards { 1 } else { 0 };
+        let mut quote = None;
+
+        while let Some(value) = if forwards { self.next() } else { self.prev() } {
+            match (quote, parse_instruction(value as char)) {
+                (Some(current), _) if current == value => quote = None,
+                (Some(_), _) => {}
+                (None, Some(ToggleStringMode { .. })) => quote = Some(value),
+                (None, Some(instr @ (StartLoop | EndLoop))) => {
+                    let is_matching = instr == if forwards { EndLoop } else { StartLoop };
+
+                    if is_matching {
+                        if depth > 1 {
+                            depth -= 1;
+                        } else {
+                            return true;
+                        }
                     } else {
-                        break;
+                        depth += 1;
                     }
-                } else {
-                    depth += 1;
                 }
+                _ => {}
             }
         }
-    }
 
-    fn jump_loop_forwards(&mut self) {
-        self.jump_loop(true);
+        false
     }
 
-    fn jump_loop_backwards(&mut self) {
-        self.jump_loop(false);
-    }
+    /// Capture the state of this tape. `pointers` is the pointer stack of
+    /// the program which the tape belongs to.
+    fn snapshot(&self, pointers: &[Rc<RefCell<Pointer>>]) -> TapeSnapshot;
 }
 
-pub trait EvalTape {
-    fn peek_next(&self) -> Option<u8>;
-    fn next(&mut self) -> Option<u8>;
-    fn prev(&mut self) -> Option<u8>;
+/// Where an [`EvalTape`] will read its next value from.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize)]
+pub enum TapeLocation {
+    /// Offset into the source code.
+    Source(usize),
+    /// Position in the grid.
+    Grid(Position),
+}
+
+impl fmt::Display for TapeLocation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TapeLocation::Source(offset) => write!(f, "source offset {}", offset),
+            TapeLocation::Grid(position) => write!(f, "grid {}", position),
+        }
+    }
 }
 
 struct GridTape<G: Grid> {
@@ -338,14 +415,10 @@ struct GridTape<G: Grid> {
 
 impl<G: Grid> EvalTape for GridTape<G> {
     fn peek_next(&self) -> Option<u8> {
-        let pointer = &mut self.pointer.borrow_mut();
+        let pointer = self.pointer.borrow();
         let value = self.grid.borrow().get(&pointer.position);
 
         if value != 0 {
-            // move the pointer forward
-            let delta = pointer.direction.unit_vector();
-            pointer.position = pointer.position + delta;
-
             Some(value)
         } else {
             None
@@ -359,7 +432,7 @@ impl<G: Grid> EvalTape for GridTape<G> {
             // advance
             let pointer = &mut self.pointer.borrow_mut();
             let delta = pointer.direction.unit_vector();
-            pointer.position = pointer.position + delta;
+            pointer.position = self.grid.borrow().wrap(pointer.position + delta);
         }
 
         value
@@ -372,14 +445,40 @@ impl<G: Grid> EvalTape for GridTape<G> {
         let delta = pointer.direction
             .opposite()
             .unit_vector();
-        pointer.position = pointer.position + delta;
+        pointer.position = self.grid.borrow().wrap(pointer.position + delta);
 
+        // like `next`, the tape ends at the first empty cell
         let value = self.grid.borrow().get(&pointer.position);
-        Some(value)
+
+        if value != 0 {
+            Some(value)
+        } else {
+            None
+        }
+    }
+
+    fn location(&self) -> TapeLocation {
+        TapeLocation::Grid(self.pointer.borrow().position)
+    }
+
+    fn seek(&mut self, location: TapeLocation) {
+        if let TapeLocation::Grid(position) = location {
+            self.pointer.borrow_mut().position = position;
+        }
+    }
+
+    fn snapshot(&self, pointers: &[Rc<RefCell<Pointer>>]) -> TapeSnapshot {
+        // a grid tape is always pushed above the pointer it reads with, and
+        // popped before it
+        let pointer = pointers.iter()
+            .position(|pointer| Rc::ptr_eq(pointer, &self.pointer))
+            .expect("grid tape pointer is not on the pointer stack");
+
+        TapeSnapshot::Grid { pointer }
     }
 }
 
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct Pointer {
     pub position: Position,
     pub direction: Direction,
@@ -405,7 +504,7 @@ impl Pointer {
     }
 }
 
-#[derive(Copy, Clone, Debug, Default, Hash, PartialEq, Eq)]
+#[derive(Copy, Clone, Debug, Default, Hash, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Position {
     pub x: isize,
     pub y: isize,
@@ -419,6 +518,12 @@ impl Position {
     }
 }
 
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "({}, {})", self.x, self.y)
+    }
+}
+
 impl Add for Position {
     type Output = Self;
 
@@ -449,7 +554,7 @@ impl Mul<isize> for Position {
     }
 }
 
-#[derive(Copy, Clone, Debug, PartialEq)]
+#[derive(Copy, Clone, Debug, PartialEq, Serialize)]
 pub enum Instruction {
     SetDirection(Direction),
     MoveOne,
@@ -479,32 +584,42 @@ pub enum Instruction {
     LogicalAnd,
     LogicalOr,
     LogicalNot,
+    /// Set the pointer value to a uniformly distributed random byte.
     Random,
     Write { kind: IOKind },
     Read { kind: IOKind },
 }
 
-#[derive(Copy, Clone, Debug, PartialEq)]
+impl Instruction {
+    /// Whether executing this instruction reads the grid cell under the
+    /// pointer.
+    pub fn reads_cell(&self) -> bool {
+        use Instruction::*;
+
+        matches!(
+            self,
+            ReadValue | SwapValue | Add | Subtract | Multiply | Divide | Modulo
+                | Equals | GreaterThan | LogicalAnd | LogicalOr | Write { .. }
+        )
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize)]
 pub enum IOKind {
     Character,
     String,
     Number,
 }
 
-#[derive(Copy, Clone, Debug, PartialEq)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
 pub enum Direction {
+    #[default]
     Right,
     Left,
     Up,
     Down,
 }
 
-impl Default for Direction {
-    fn default() -> Self {
-        Direction::Right
-    }
-}
-
 impl Direction {
     pub fn opposite(&self) -> Direction {
         use Direction::*;
@@ -529,7 +644,7 @@ impl Direction {
     }
 }
 
-#[derive(Copy, Clone, Debug, PartialEq)]
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum StringModeKind {
     Single,
     Double,
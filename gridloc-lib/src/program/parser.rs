@@ -1,6 +1,19 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
 use crate::*;
 
 pub const COMMENT_CHAR: char = '#';
+pub const ESCAPE_CHAR: char = '\\';
+
+/// The character which `instruction` is written as.
+pub fn instruction_char(instruction: Instruction) -> char {
+    (0..=127u8)
+        .map(char::from)
+        .find(|c| parse_instruction(*c) == Some(instruction))
+        .expect("every instruction has a character")
+}
 
 pub fn parse_instruction(c: char) -> Option<Instruction> {
     use Instruction::*;
@@ -28,7 +41,7 @@ pub fn parse_instruction(c: char) -> Option<Instruction> {
         'e' => Evaluate,
         '@' => Kill,
         '0'..='9' => Value(c.to_digit(10).unwrap() as u8),
-        'A'..='F' => Value(c as u8 - 'A' as u8 + 10),
+        'A'..='F' => Value(c as u8 - b'A' + 10),
         '+' => Add,
         '-' => Subtract,
         '*' => Multiply,
@@ -50,18 +63,274 @@ pub fn parse_instruction(c: char) -> Option<Instruction> {
     })
 }
 
-pub fn clean_source(source: &str) -> String {
-    let mut buffer = String::with_capacity(source.len());
+/// The loop brackets of source code, matched with their partners ahead of
+/// time. Brackets inside string literals are data, so they are skipped.
+#[derive(Clone, Debug)]
+pub struct Brackets {
+    partners: Vec<Option<usize>>,
+    unmatched: Vec<usize>,
+}
+
+impl Brackets {
+    pub fn new(source: &[u8]) -> Self {
+        let mut partners = vec![None; source.len()];
+        let mut unmatched = Vec::new();
+        let mut open = Vec::new();
+        let mut quote = None;
+
+        for (offset, value) in source.iter().enumerate() {
+            match (quote, parse_instruction(*value as char)) {
+                (Some(current), _) if current == *value => quote = None,
+                (Some(_), _) => {}
+                (None, Some(Instruction::ToggleStringMode { .. })) => quote = Some(*value),
+                (None, Some(Instruction::StartLoop)) => open.push(offset),
+                (None, Some(Instruction::EndLoop)) => match open.pop() {
+                    Some(start) => {
+                        partners[start] = Some(offset);
+                        partners[offset] = Some(start);
+                    }
+                    None => unmatched.push(offset),
+                },
+                _ => {}
+            }
+        }
+
+        unmatched.extend(open);
+        unmatched.sort_unstable();
+
+        Self {
+            partners,
+            unmatched,
+        }
+    }
+
+    /// The offset of the bracket matching the one at `offset`.
+    pub fn partner(&self, offset: usize) -> Option<usize> {
+        self.partners.get(offset).copied().flatten()
+    }
+
+    /// The offsets of the brackets without a partner, in order.
+    pub fn unmatched(&self) -> &[usize] {
+        &self.unmatched
+    }
+
+    /// Describe the brackets without a partner in `source`, which is the
+    /// output of [`clean_source_mapped`] along with `source_map`.
+    pub fn describe_unmatched(&self, source: &[u8], source_map: &SourceMap) -> Vec<UnmatchedBracket> {
+        self.unmatched.iter()
+            .map(|&offset| UnmatchedBracket {
+                bracket: source[offset] as char,
+                offset,
+                position: source_map.position(offset).unwrap_or(SourcePosition { line: 1, column: offset + 1 }),
+            })
+            .collect()
+    }
+}
+
+/// A line and column of source code, both counting from 1.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct SourcePosition {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for SourcePosition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}, column {}", self.line, self.column)
+    }
+}
 
-    for line in source.lines() {
-        let line = line.trim();
+/// Where every value of cleaned source code came from in the original.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct SourceMap {
+    positions: Vec<SourcePosition>,
+    #[serde(skip)]
+    comments: Vec<Comment>,
+}
 
-        if let Some(idx) = line.find(COMMENT_CHAR) {
-            buffer.push_str(&line[..idx]);
-        } else {
-            buffer.push_str(&line);
-        };
+impl SourceMap {
+    /// The original position of the value at `offset`.
+    pub fn position(&self, offset: usize) -> Option<SourcePosition> {
+        self.positions.get(offset).copied()
     }
 
-    buffer
+    /// The offset of the first value at or after `position` on the same
+    /// line.
+    pub fn offset(&self, position: SourcePosition) -> Option<usize> {
+        self.positions.iter()
+            .position(|p| p.line == position.line && p.column >= position.column)
+    }
+
+    /// The comments which were stripped, in the order they appeared.
+    pub fn comments(&self) -> &[Comment] {
+        &self.comments
+    }
+}
+
+/// A comment of the original source code.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Comment {
+    /// Position of the `#` which started it.
+    pub position: SourcePosition,
+    /// Everything after the `#` up to the end of the line.
+    pub text: String,
+}
+
+/// Strip comments and whitespace from `source`, keeping track of where each
+/// of the remaining values came from.
+///
+/// String literals are kept exactly as they are written, including any line
+/// breaks, and a `#` inside of one does not start a comment. Inside a
+/// literal, a backslash starts an escape:
+///
+/// - `\n`, `\r`, `\t` and `\0` for a line feed, carriage return, tab and
+///   zero byte,
+/// - `\xNN` for the byte with the hexadecimal value `NN`,
+/// - `\\` for a backslash, and a backslash before the closing quote for the
+///   quote itself.
+///
+/// A backslash which starts none of these is kept as it is.
+pub fn clean_source_mapped(source: &str) -> (Vec<u8>, SourceMap) {
+    let mut cleaned = Cleaned {
+        buffer: Vec::with_capacity(source.len()),
+        positions: Vec::with_capacity(source.len()),
+        quote: None,
+    };
+
+    let mut comments = Vec::new();
+    let mut line_end = None;
+
+    for (index, line) in source.lines().enumerate() {
+        // a literal continues over the line break
+        if let Some(position) = line_end {
+            if cleaned.quote.is_some() {
+                cleaned.push_byte(b'\n', position);
+            }
+        }
+
+        let mut chars = line.chars()
+            .enumerate()
+            .map(|(column, c)| (SourcePosition { line: index + 1, column: column + 1 }, c))
+            .peekable();
+
+        // whitespace outside of literals is only kept between other values
+        let mut pending = Vec::new();
+        let mut started = false;
+
+        while let Some((position, c)) = chars.next() {
+            let current = match cleaned.quote {
+                Some(current) => {
+                    started = true;
+                    current
+                }
+                None if c.is_whitespace() => {
+                    if started {
+                        pending.push((position, c));
+                    }
+                    continue;
+                }
+                None => {
+                    started = true;
+                    for (position, c) in pending.drain(..) {
+                        cleaned.push(c, position);
+                    }
+
+                    if c == COMMENT_CHAR {
+                        let text = chars.map(|(_, c)| c).collect();
+                        comments.push(Comment { position, text });
+                        break;
+                    }
+
+                    if let Some(Instruction::ToggleStringMode { .. }) = parse_instruction(c) {
+                        cleaned.quote = Some(c as u8);
+                    }
+
+                    cleaned.push(c, position);
+                    continue;
+                }
+            };
+
+            if c == ESCAPE_CHAR {
+                if let Some(byte) = escape(&mut chars, current) {
+                    cleaned.push_byte(byte, position);
+                    continue;
+                }
+            } else if c as u32 == current as u32 {
+                cleaned.quote = None;
+            }
+
+            cleaned.push(c, position);
+        }
+
+        line_end = Some(SourcePosition { line: index + 1, column: line.chars().count() + 1 });
+    }
+
+    (cleaned.buffer, SourceMap { positions: cleaned.positions, comments })
+}
+
+/// Read the rest of an escape inside a literal opened by `quote`, returning
+/// the byte it stands for. Nothing is read if it is not an escape.
+fn escape<I: Iterator<Item = (SourcePosition, char)> + Clone>(chars: &mut std::iter::Peekable<I>, quote: u8) -> Option<u8> {
+    let (_, next) = *chars.peek()?;
+
+    let byte = match next {
+        'n' => b'\n',
+        'r' => b'\r',
+        't' => b'\t',
+        '0' => 0,
+        ESCAPE_CHAR => b'\\',
+        'x' => {
+            // look ahead without consuming, in case the digits are missing
+            let mut ahead = chars.clone();
+            ahead.next();
+            let high = ahead.next()?.1.to_digit(16)?;
+            let low = ahead.next()?.1.to_digit(16)?;
+
+            chars.next();
+            chars.next();
+            (high << 4 | low) as u8
+        }
+        c if c as u32 == quote as u32 => quote,
+        _ => return None,
+    };
+
+    chars.next();
+    Some(byte)
+}
+
+struct Cleaned {
+    buffer: Vec<u8>,
+    positions: Vec<SourcePosition>,
+    /// The quote which opened the literal we are in, if any.
+    quote: Option<u8>,
+}
+
+impl Cleaned {
+    fn push(&mut self, c: char, position: SourcePosition) {
+        let mut bytes = [0; 4];
+        self.buffer.extend_from_slice(c.encode_utf8(&mut bytes).as_bytes());
+        self.positions.extend((0..c.len_utf8()).map(|_| position));
+    }
+
+    /// Push a byte of a literal.
+    fn push_byte(&mut self, byte: u8, position: SourcePosition) {
+        match self.quote {
+            // the quote would end the literal, so end it, write the quote
+            // from a literal of the other kind, and start it again
+            Some(quote) if byte == quote => {
+                let other = if quote == b'\'' { b'"' } else { b'\'' };
+
+                self.buffer.extend_from_slice(&[quote, other, quote, other, quote]);
+                self.positions.extend((0..5).map(|_| position));
+            }
+            _ => {
+                self.buffer.push(byte);
+                self.positions.push(position);
+            }
+        }
+    }
+}
+
+pub fn clean_source(source: &str) -> Vec<u8> {
+    clean_source_mapped(source).0
 }
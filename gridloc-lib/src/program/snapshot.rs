@@ -0,0 +1,234 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::error;
+use std::fmt;
+use std::io;
+use std::io::{Read, Write};
+use std::rc::Rc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::*;
+
+use super::GridTape;
+
+/// Version of the snapshot format written by this version of the library.
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+/// The complete state of a program, which can be saved and resumed later,
+/// even with a different kind of [`Grid`].
+///
+/// The random number generator is not part of a snapshot, so a resumed
+/// program draws different random values than the original would have.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Snapshot {
+    version: u32,
+    cells: Vec<(Position, u8)>,
+    pointers: Vec<Pointer>,
+    tapes: Vec<TapeSnapshot>,
+    saved_positions: BTreeMap<u8, Position>,
+    string_mode: Option<StringModeKind>,
+    steps: u64,
+    output_bytes: usize,
+}
+
+/// The state of a single [`EvalTape`].
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum TapeSnapshot {
+    /// A tape over source code, with the index of the next value.
+    Source {
+        source: Vec<u8>,
+        index: usize,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        source_map: Option<SourceMap>,
+    },
+    /// A tape which evaluates the grid under the pointer with this index in
+    /// the pointer stack.
+    Grid { pointer: usize },
+}
+
+/// Just enough of a snapshot to find out whether the rest can be read.
+#[derive(Deserialize)]
+struct Header {
+    version: u32,
+}
+
+impl Snapshot {
+    /// Write the snapshot to `writer`, flushing it afterwards.
+    pub fn write_to<W: Write>(&self, mut writer: W) -> Result<(), SnapshotError> {
+        serde_json::to_writer(&mut writer, self)?;
+        writer.flush()?;
+
+        Ok(())
+    }
+
+    /// Read a snapshot from `reader`. Whether it can be resumed is only
+    /// checked once it is.
+    pub fn read_from<R: Read>(mut reader: R) -> Result<Self, SnapshotError> {
+        let mut buffer = Vec::new();
+        reader.read_to_end(&mut buffer)?;
+
+        // check the version first, as the rest may be in another format
+        let header: Header = serde_json::from_slice(&buffer)?;
+        if header.version != SNAPSHOT_VERSION {
+            return Err(SnapshotError::Version(header.version));
+        }
+
+        Ok(serde_json::from_slice(&buffer)?)
+    }
+
+    /// Fail if the snapshot can not be resumed, as it may have been written
+    /// by hand or by another version.
+    fn validate(&self) -> Result<(), SnapshotError> {
+        if self.version != SNAPSHOT_VERSION {
+            return Err(SnapshotError::Version(self.version));
+        }
+
+        // each tape is pushed and popped along with a pointer
+        if self.pointers.len() != self.tapes.len() {
+            return Err(SnapshotError::UnevenStacks { pointers: self.pointers.len(), tapes: self.tapes.len() });
+        }
+
+        // and a grid tape reads with a pointer which was there before it
+        for (index, tape) in self.tapes.iter().enumerate() {
+            if let TapeSnapshot::Grid { pointer } = tape {
+                if *pointer >= index {
+                    return Err(SnapshotError::UnboundTape(*pointer));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<'a, G: 'a + Grid, Rng: rand::Rng> ProgramState<'a, G, Rng> {
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            version: SNAPSHOT_VERSION,
+            cells: self.grid.borrow().cells().collect(),
+            pointers: self.pointers.iter()
+                .map(|pointer| pointer.borrow().clone())
+                .collect(),
+            tapes: self.eval_tapes.iter()
+                .map(|tape| tape.snapshot(&self.pointers))
+                .collect(),
+            saved_positions: self.saved_positions.clone(),
+            string_mode: self.string_mode,
+            steps: self.steps,
+            output_bytes: self.output_bytes,
+        }
+    }
+
+    /// Resume from `snapshot`, failing if it is not one which this version
+    /// can resume.
+    pub fn from_snapshot(snapshot: Snapshot, rng: Rng) -> Result<Self, SnapshotError> {
+        Self::from_snapshot_on(snapshot, G::default(), rng)
+    }
+
+    /// Resume from `snapshot` like [`from_snapshot`](Self::from_snapshot),
+    /// with the cells written to `grid`, which should be empty.
+    pub fn from_snapshot_on(snapshot: Snapshot, mut grid: G, rng: Rng) -> Result<Self, SnapshotError> {
+        snapshot.validate()?;
+
+        for (position, value) in snapshot.cells {
+            grid.try_set(&position, value).ok_or(SnapshotError::GridSize)?;
+        }
+        let grid = Rc::new(RefCell::new(grid));
+
+        let pointers: Vec<_> = snapshot.pointers.into_iter()
+            .map(|pointer| Rc::new(RefCell::new(pointer)))
+            .collect();
+
+        let eval_tapes = snapshot.tapes.into_iter()
+            .map(|tape| -> Box<dyn EvalTape + 'a> {
+                match tape {
+                    TapeSnapshot::Source { source, index, source_map } => {
+                        let tape = SourceTape::new(source, index);
+
+                        match source_map {
+                            Some(source_map) => Box::new(tape.with_source_map(source_map)),
+                            None => Box::new(tape),
+                        }
+                    }
+                    TapeSnapshot::Grid { pointer } => Box::new(GridTape {
+                        pointer: pointers[pointer].clone(),
+                        grid: grid.clone(),
+                    }),
+                }
+            })
+            .collect();
+
+        let mut state = Self::new(Box::new(SourceTape::from("")), rng);
+        state.eval_tapes = eval_tapes;
+        state.grid = grid;
+        state.pointers = pointers;
+        state.saved_positions = snapshot.saved_positions;
+        state.string_mode = snapshot.string_mode;
+        state.steps = snapshot.steps;
+        state.output_bytes = snapshot.output_bytes;
+
+        Ok(state)
+    }
+}
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(io::Error),
+    /// The snapshot is not valid JSON of the expected shape.
+    Format(serde_json::Error),
+    /// The snapshot was written in an unsupported version of the format.
+    Version(u32),
+    /// There is not exactly one pointer for each tape.
+    UnevenStacks { pointers: usize, tapes: usize },
+    /// A grid tape is bound to a pointer index which does not exist below
+    /// the tape.
+    UnboundTape(usize),
+    /// The cells do not fit in the grid, such as a [`DenseGrid`] which may
+    /// not grow that large.
+    GridSize,
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SnapshotError::Io(error) => write!(f, "I/O error: {}", error),
+            SnapshotError::Format(error) => write!(f, "malformed snapshot: {}", error),
+            SnapshotError::Version(version) => write!(
+                f,
+                "unsupported snapshot version {} (expected {})",
+                version,
+                SNAPSHOT_VERSION,
+            ),
+            SnapshotError::UnevenStacks { pointers, tapes } => {
+                write!(f, "there are {} pointers but {} tapes, instead of one for each", pointers, tapes)
+            }
+            SnapshotError::UnboundTape(pointer) => {
+                write!(f, "grid tape is bound to pointer {}, which is not below it", pointer)
+            }
+            SnapshotError::GridSize => write!(f, "the cells do not fit in the grid"),
+        }
+    }
+}
+
+impl error::Error for SnapshotError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            SnapshotError::Io(error) => Some(error),
+            SnapshotError::Format(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for SnapshotError {
+    fn from(error: io::Error) -> Self {
+        SnapshotError::Io(error)
+    }
+}
+
+impl From<serde_json::Error> for SnapshotError {
+    fn from(error: serde_json::Error) -> Self {
+        SnapshotError::Format(error)
+    }
+}
@@ -0,0 +1,359 @@
+use std::fmt;
+use std::mem;
+
+use crate::*;
+
+/// Source code parsed into its structure. Comments and line breaks are kept,
+/// so that the program can be printed again in a canonical layout.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Program {
+    pub items: Vec<Item>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Item {
+    /// Any instruction except for loop brackets and quotes.
+    Instruction(Instruction),
+    /// A loop, with the items between its brackets.
+    Loop(Vec<Item>),
+    /// A string literal, with its escapes decoded. A literal at the very end
+    /// of the source code may be missing its closing quote.
+    Literal { kind: StringModeKind, bytes: Vec<u8>, closed: bool },
+    /// A comment, without the `#` and the whitespace around it. A comment
+    /// always ends its line.
+    Comment(String),
+    /// A value which is not an instruction, and so does nothing.
+    Other(char),
+    LineBreak,
+}
+
+/// The width of one level of indentation inside of a loop.
+const INDENT: &str = "    ";
+
+impl Program {
+    /// Parse `source`, which is read by [`clean_source_mapped`] first. The
+    /// line breaks and comments are put back from its [`SourceMap`].
+    pub fn parse(source: &str) -> Result<Self, BuildError> {
+        use Instruction::*;
+
+        let (cleaned, source_map) = clean_source_mapped(source);
+        let unmatched = Brackets::new(&cleaned).describe_unmatched(&cleaned, &source_map);
+        if !unmatched.is_empty() {
+            return Err(BuildError::UnmatchedBrackets(unmatched));
+        }
+
+        let position = |offset: usize| source_map.position(offset).expect("every value has a position");
+
+        let mut parser = Parser {
+            items: Vec::new(),
+            outer_items: Vec::new(),
+            comments: source_map.comments(),
+            line: 1,
+        };
+        let mut offset = 0;
+
+        while offset < cleaned.len() {
+            let c = char_at(&cleaned, offset);
+            parser.end_lines(position(offset).line);
+            offset += c.len_utf8();
+
+            if c.is_whitespace() {
+                continue;
+            }
+
+            let kind = match parse_instruction(c) {
+                Some(ToggleStringMode { kind }) => kind,
+                Some(StartLoop) => {
+                    parser.outer_items.push(mem::take(&mut parser.items));
+                    continue;
+                }
+                Some(EndLoop) => {
+                    let outer = parser.outer_items.pop().expect("brackets were matched");
+                    let body = mem::replace(&mut parser.items, outer);
+                    parser.items.push(Item::Loop(body));
+                    continue;
+                }
+                Some(instruction) => {
+                    parser.items.push(Item::Instruction(instruction));
+                    continue;
+                }
+                None => {
+                    parser.items.push(Item::Other(c));
+                    continue;
+                }
+            };
+
+            // the escapes of the literal are decoded already
+            let quote = c as u8;
+            let mut bytes = Vec::new();
+            let mut closed = false;
+
+            while let Some(&value) = cleaned.get(offset) {
+                parser.line = position(offset).line;
+
+                if value != quote {
+                    bytes.push(value);
+                    offset += 1;
+                } else if is_escaped_quote(&cleaned, &source_map, offset) {
+                    bytes.push(quote);
+                    offset += ESCAPED_QUOTE_LEN;
+                } else {
+                    offset += 1;
+                    closed = true;
+                    break;
+                }
+            }
+
+            parser.items.push(Item::Literal { kind, bytes, closed });
+
+            // the lines of a literal which never ends are all its own
+            if !closed {
+                return Ok(Self { items: parser.items });
+            }
+        }
+
+        parser.end_lines(source.lines().count() + 1);
+
+        Ok(Self { items: parser.items })
+    }
+}
+
+struct Parser<'s> {
+    items: Vec<Item>,
+    /// The items around each loop we are in, innermost last.
+    outer_items: Vec<Vec<Item>>,
+    /// The comments which are yet to be put back.
+    comments: &'s [Comment],
+    /// The line of the last value.
+    line: usize,
+}
+
+impl Parser<'_> {
+    /// End every line before `line`, each with its comment if it has one.
+    fn end_lines(&mut self, line: usize) {
+        while self.line < line {
+            match self.comments.split_first() {
+                Some((comment, rest)) if comment.position.line == self.line => {
+                    self.items.push(Item::Comment(comment.text.trim().to_string()));
+                    self.comments = rest;
+                }
+                _ => self.items.push(Item::LineBreak),
+            }
+
+            self.line += 1;
+        }
+    }
+}
+
+/// How many values [`clean_source_mapped`] writes for a quote inside of a
+/// literal, which it ends and starts again around the quote.
+const ESCAPED_QUOTE_LEN: usize = 5;
+
+/// Whether the quote at `offset` of cleaned source code is one which was
+/// escaped. All the values written for it come from the same escape.
+fn is_escaped_quote(cleaned: &[u8], source_map: &SourceMap, offset: usize) -> bool {
+    let last = offset + ESCAPED_QUOTE_LEN - 1;
+
+    last < cleaned.len() && source_map.position(offset) == source_map.position(last)
+}
+
+/// The character which starts at `offset` of cleaned source code, outside
+/// of any literal. Only literals may hold bytes which are not UTF-8.
+fn char_at(cleaned: &[u8], offset: usize) -> char {
+    let len = match cleaned[offset].leading_ones() {
+        0 => 1,
+        len => len as usize,
+    };
+
+    std::str::from_utf8(&cleaned[offset..offset + len])
+        .ok()
+        .and_then(|text| text.chars().next())
+        .expect("source code outside of literals is UTF-8")
+}
+
+/// Prints the program in its canonical layout: no whitespace between
+/// values, at most one blank line in a row, and the body of every loop which
+/// spans several lines indented on lines of its own.
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut printer = Printer {
+            out: String::new(),
+            indent: 0,
+            breaks: 0,
+            line_is_empty: true,
+        };
+        printer.items(&self.items);
+
+        if !printer.out.is_empty() {
+            printer.out.push('\n');
+        }
+
+        f.write_str(&printer.out)
+    }
+}
+
+struct Printer {
+    out: String,
+    indent: usize,
+    /// Line breaks to write before the next value.
+    breaks: usize,
+    line_is_empty: bool,
+}
+
+impl Printer {
+    fn items(&mut self, items: &[Item]) {
+        // line breaks at the start and end of a block are left out
+        let start = items.iter().position(|item| *item != Item::LineBreak).unwrap_or(items.len());
+        let end = items.iter().rposition(|item| *item != Item::LineBreak).map_or(start, |end| end + 1);
+
+        for item in &items[start..end] {
+            self.item(item);
+        }
+    }
+
+    fn item(&mut self, item: &Item) {
+        match item {
+            Item::Instruction(instruction) => self.write(&instruction_char(*instruction).to_string()),
+            Item::Loop(body) if is_multiline(body) => {
+                self.write("[");
+                self.indent += 1;
+                self.breaks = 1;
+                self.items(body);
+                self.indent -= 1;
+                self.breaks = 1;
+                self.write("]");
+            }
+            Item::Loop(body) => {
+                self.write("[");
+                self.items(body);
+                self.write("]");
+            }
+            Item::Literal { kind, bytes, closed } => {
+                let quote = match kind {
+                    StringModeKind::Single => '\'',
+                    StringModeKind::Double => '"',
+                };
+
+                let mut text = quote.to_string();
+                escape_literal(bytes, quote, &mut text);
+                if *closed {
+                    text.push(quote);
+                }
+
+                self.write(&text);
+            }
+            Item::Comment(text) => {
+                let separator = if self.breaks == 0 && !self.line_is_empty { "  " } else { "" };
+
+                if text.is_empty() {
+                    self.write(&format!("{}{}", separator, COMMENT_CHAR));
+                } else {
+                    self.write(&format!("{}{} {}", separator, COMMENT_CHAR, text));
+                }
+
+                self.breaks = 1;
+            }
+            Item::Other(c) => self.write(&c.to_string()),
+            Item::LineBreak => self.breaks = (self.breaks + 1).min(2),
+        }
+    }
+
+    fn write(&mut self, text: &str) {
+        if self.breaks > 0 {
+            for _ in 0..self.breaks {
+                self.out.push('\n');
+            }
+
+            self.breaks = 0;
+            self.line_is_empty = true;
+        }
+
+        if self.line_is_empty {
+            for _ in 0..self.indent {
+                self.out.push_str(INDENT);
+            }
+        }
+
+        self.out.push_str(text);
+        self.line_is_empty = false;
+    }
+}
+
+/// Whether a loop with `body` spans several lines.
+fn is_multiline(body: &[Item]) -> bool {
+    body.iter().any(|item| match item {
+        Item::LineBreak | Item::Comment(_) => true,
+        Item::Loop(body) => is_multiline(body),
+        _ => false,
+    })
+}
+
+/// Write `bytes` as the contents of a literal opened by `quote`, so that
+/// they are read back the same way.
+fn escape_literal(bytes: &[u8], quote: char, out: &mut String) {
+    let write_char = |c: char, out: &mut String| match c {
+        _ if c == quote || c == ESCAPE_CHAR => {
+            out.push(ESCAPE_CHAR);
+            out.push(c);
+        }
+        // line breaks are kept, so that text keeps its layout
+        '\n' => out.push('\n'),
+        '\r' => out.push_str("\\r"),
+        '\t' => out.push_str("\\t"),
+        '\0' => out.push_str("\\0"),
+        _ if c.is_control() => out.push_str(&format!("\\x{:02X}", c as u32)),
+        _ => out.push(c),
+    };
+
+    match std::str::from_utf8(bytes) {
+        Ok(text) => text.chars().for_each(|c| write_char(c, out)),
+        Err(_) => {
+            for byte in bytes {
+                if byte.is_ascii() {
+                    write_char(*byte as char, out);
+                } else {
+                    out.push_str(&format!("\\x{:02X}", byte));
+                }
+            }
+        }
+    }
+}
+
+/// The values of `source` which affect what it does: its cleaned form,
+/// without the whitespace between instructions, which only takes up steps.
+/// Formatting a program never changes these.
+pub fn significant_code(source: &str) -> Vec<u8> {
+    let cleaned = clean_source(source);
+    let mut code = Vec::with_capacity(cleaned.len());
+    let mut quote = None;
+    let mut offset = 0;
+
+    while offset < cleaned.len() {
+        let value = cleaned[offset];
+
+        if let Some(current) = quote {
+            if current == value {
+                quote = None;
+            }
+
+            code.push(value);
+            offset += 1;
+            continue;
+        }
+
+        let c = char_at(&cleaned, offset);
+        let end = offset + c.len_utf8();
+
+        if !c.is_whitespace() {
+            if let Some(Instruction::ToggleStringMode { .. }) = parse_instruction(c) {
+                quote = Some(value);
+            }
+
+            code.extend_from_slice(&cleaned[offset..end]);
+        }
+
+        offset = end;
+    }
+
+    code
+}
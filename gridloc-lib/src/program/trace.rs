@@ -0,0 +1,166 @@
+use std::cell::RefCell;
+use std::io;
+use std::io::Write;
+use std::rc::Rc;
+
+use serde::Serialize;
+
+use crate::*;
+
+/// Receives a record of every step which reads a value from a tape.
+pub trait Tracer {
+    fn trace(&mut self, record: &TraceRecord) -> io::Result<()>;
+
+    /// Write out any records which are still buffered. This is called once
+    /// a run ends.
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Writes every record as a single line of JSON.
+pub struct JsonLinesTracer<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> JsonLinesTracer<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+        }
+    }
+}
+
+impl<W: Write> Tracer for JsonLinesTracer<W> {
+    fn trace(&mut self, record: &TraceRecord) -> io::Result<()> {
+        serde_json::to_writer(&mut self.writer, record)?;
+        writeln!(self.writer)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// What a single step did.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct TraceRecord {
+    /// The number of the step, counting from 1.
+    pub step: u64,
+    /// How deeply the tape which was read is nested.
+    pub depth: usize,
+    /// Where the value was read from.
+    pub location: TapeLocation,
+    /// Where the value came from in the original source code, if known.
+    pub source: Option<SourcePosition>,
+    /// The value which was read.
+    pub value: u8,
+    /// The instruction which was executed, if the value was not data.
+    pub instruction: Option<Instruction>,
+    /// The pointer which executed the step, before and after it.
+    pub before: Option<PointerTrace>,
+    pub after: Option<PointerTrace>,
+    /// The grid cells which the step wrote, in order.
+    pub writes: Vec<CellWrite>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize)]
+pub struct PointerTrace {
+    pub position: Position,
+    pub direction: Direction,
+    pub value: u8,
+}
+
+impl From<&Pointer> for PointerTrace {
+    fn from(pointer: &Pointer) -> Self {
+        Self {
+            position: pointer.position,
+            direction: pointer.direction,
+            value: pointer.value,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize)]
+pub struct CellWrite {
+    pub position: Position,
+    pub old: u8,
+    pub new: u8,
+}
+
+/// The part of a record which is known before the step runs.
+pub(super) struct PendingTrace {
+    steps: u64,
+    depth: usize,
+    location: TapeLocation,
+    source: Option<SourcePosition>,
+    value: u8,
+    instruction: Option<Instruction>,
+    pointer: Option<Rc<RefCell<Pointer>>>,
+    before: Option<PointerTrace>,
+}
+
+impl<'a, G: 'a + Grid, Rng: rand::Rng> ProgramState<'a, G, Rng> {
+    /// Pass a record of every step to `tracer`.
+    pub fn set_tracer(&mut self, tracer: Box<dyn Tracer + 'a>) {
+        self.tracer = Some(tracer);
+    }
+
+    /// Write out the records which the tracer still buffers. [`run`](Self::run)
+    /// does so before it returns, but a program which is run step by step
+    /// must be flushed once it is done.
+    pub fn flush_tracer(&mut self) -> Result<(), Error> {
+        match &mut self.tracer {
+            Some(tracer) => tracer.flush().map_err(Error::Io),
+            None => Ok(()),
+        }
+    }
+
+    pub(super) fn begin_trace(&self) -> Option<PendingTrace> {
+        let tape = self.current_tape()?;
+        let pointer = self.current_pointer();
+
+        Some(PendingTrace {
+            steps: self.steps,
+            depth: self.eval_tapes.len() - 1,
+            location: tape.location(),
+            source: tape.source_position(tape.location()),
+            value: tape.peek_next()?,
+            instruction: self.peek_instruction(),
+            before: pointer.as_ref().map(|pointer| PointerTrace::from(&*pointer.borrow())),
+            pointer,
+        })
+    }
+
+    /// Pass the record of the step which just ran to the tracer, unless it
+    /// did not read a value.
+    pub(super) fn finish_trace(&mut self, pending: PendingTrace) -> Result<(), Error> {
+        if self.steps == pending.steps {
+            return Ok(());
+        }
+
+        let writes = self.events.iter()
+            .filter_map(|event| match *event {
+                Event::CellWritten { position, old, new } => Some(CellWrite { position, old, new }),
+                _ => None,
+            })
+            .collect();
+
+        let record = TraceRecord {
+            step: self.steps,
+            depth: pending.depth,
+            location: pending.location,
+            source: pending.source,
+            value: pending.value,
+            instruction: pending.instruction,
+            before: pending.before,
+            after: pending.pointer.map(|pointer| PointerTrace::from(&*pointer.borrow())),
+            writes,
+        };
+
+        match &mut self.tracer {
+            Some(tracer) => tracer.trace(&record).map_err(Error::Io),
+            None => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,179 @@
+//! Compiled programs must do what the interpreter does with their source
+//! code.
+
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use gridloc::*;
+
+mod common;
+use common::Output;
+
+/// Programs and the input they are given.
+const PROGRAMS: &[(&str, &str)] = &[
+    ("'Hi'(0q)<..s>g;n", ""),
+    ("v.01,03[n(.'[')-]@", ""),
+    ("(\"[']']2A,x@\")e0A,x", ""),
+    ("(\"v.01,02[n(.'[')-]@\")e(>'ok'):s", ""),
+    // arithmetic and comparisons
+    ("07,03*,nF0/,n04%,n04=,n01`,n!,n", ""),
+    ("05,(00,)%n", ""),
+    ("05,(00,)/n", ""),
+    // input of every kind
+    ("(N)n.(X)x.(S)s", "42\nz\nrest\n"),
+    // positions saved and loaded through the pointer value
+    ("02q...03q02g'a'03g'b'02gx03gx", ""),
+    // characters which need escaping in C and Rust literals
+    ("('a\\b\"c?\t??=d\n\0')s", ""),
+    // characters and values outside of ASCII
+    ("('héllo ☃')s.FF,xC3,x(80,)n", ""),
+    ("(X)x.(S)s.(X)x", "é\nñandú\n☃\n"),
+    ("(N)n.(N)n.(N)n", "300\nabc\n"),
+];
+
+/// Programs which use `?`, whose numbers only compiled Rust draws like the
+/// interpreter does.
+const RANDOM_PROGRAMS: &[(&str, &str)] = &[("?,n(?,n)?,n", "")];
+
+/// The output and error of running `source` with the interpreter.
+fn interpret(source: &str, input: &str) -> (Vec<u8>, Option<String>) {
+    let output = Output::default();
+    let mut interpreter = InterpreterBuilder::from_source(source)
+        .reader(Box::new(io::Cursor::new(input.to_string())))
+        .writer(Box::new(output.clone()))
+        .seed(0)
+        .build::<HashGrid>()
+        .unwrap();
+    let result = interpreter.run();
+
+    (output.0.take(), result.err().map(|error| kind(&error.to_string())))
+}
+
+/// What went wrong, without where, which compiled code does not know.
+fn kind(error: &str) -> String {
+    error.split(" at ").next().unwrap().to_string()
+}
+
+/// Write a crate which runs each of `PROGRAMS` and `RANDOM_PROGRAMS`
+/// compiled to Rust, picked by its first argument, and build it.
+fn build_rust(dir: &Path) -> std::path::PathBuf {
+    let manifest = format!(
+        "[package]\nname = \"compiled\"\nversion = \"0.0.0\"\nedition = \"2021\"\n\n\
+         [dependencies]\ngridloc-lib = {{ path = {:?} }}\n\n[workspace]\n",
+        env!("CARGO_MANIFEST_DIR"),
+    );
+    fs::create_dir_all(dir.join("src")).unwrap();
+    fs::write(dir.join("Cargo.toml"), manifest).unwrap();
+
+    // build against the same versions as this crate
+    let lock = Path::new(env!("CARGO_MANIFEST_DIR")).join("../Cargo.lock");
+    if lock.exists() {
+        fs::copy(lock, dir.join("Cargo.lock")).unwrap();
+    }
+
+    let mut main = "#![allow(dead_code)]\n\n".to_string();
+    let mut arms = String::new();
+    for (i, (source, _)) in PROGRAMS.iter().chain(RANDOM_PROGRAMS).enumerate() {
+        fs::write(dir.join(format!("src/p{}.rs", i)), Target::Rust.compile(source).unwrap()).unwrap();
+        main += &format!("mod p{};\n", i);
+        arms += &format!("        {} => p{}::run_with(&mut state, &mut reader, &mut writer),\n", i, i);
+    }
+    main += &format!(
+        "\nuse gridloc::*;\n\nfn main() {{\n    \
+         let mut state = NativeState::<HashGrid>::with_seed(0);\n    \
+         let mut reader = std::io::stdin().lock();\n    \
+         let mut writer = std::io::stdout().lock();\n    \
+         let result = match std::env::args().nth(1).unwrap().parse::<usize>().unwrap() {{\n{}        \
+         _ => unreachable!(),\n    }};\n    \
+         if let Err(error) = result {{\n        eprint!(\"{{}}\", error);\n        std::process::exit(1);\n    }}\n}}\n",
+        arms,
+    );
+    fs::write(dir.join("src/main.rs"), main).unwrap();
+
+    let cargo = std::env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
+    let status = Command::new(cargo)
+        .args(["build", "--quiet", "--offline", "--manifest-path"])
+        .arg(dir.join("Cargo.toml"))
+        .arg("--target-dir")
+        .arg(dir.join("target"))
+        .status()
+        .unwrap();
+    assert!(status.success(), "the compiled programs do not build");
+
+    dir.join("target/debug/compiled")
+}
+
+/// The output of running `command` on `input`, and what it printed to
+/// stderr if it failed.
+fn run_compiled(command: &mut Command, input: &str) -> (Vec<u8>, Option<String>) {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(input.as_bytes()).unwrap();
+    let compiled = child.wait_with_output().unwrap();
+    let error = (!compiled.status.success()).then(|| String::from_utf8(compiled.stderr).unwrap());
+
+    (compiled.stdout, error)
+}
+
+#[test]
+fn compiled_rust_does_what_the_interpreter_does() {
+    let binary = build_rust(&Path::new(env!("CARGO_TARGET_TMPDIR")).join("compiled-rust"));
+
+    for (i, (source, input)) in PROGRAMS.iter().chain(RANDOM_PROGRAMS).enumerate() {
+        let (output, error) = run_compiled(Command::new(&binary).arg(i.to_string()), input);
+
+        assert_eq!((output, error.as_deref().map(kind)), interpret(source, input), "{}", source);
+    }
+}
+
+#[test]
+fn compiled_c_does_what_the_interpreter_does() {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("compiled-c");
+    fs::create_dir_all(&dir).unwrap();
+
+    for (i, (source, input)) in PROGRAMS.iter().enumerate() {
+        let c_file = dir.join(format!("p{}.c", i));
+        let binary = dir.join(format!("p{}", i));
+        fs::write(&c_file, Target::C.compile(source).unwrap()).unwrap();
+
+        let status = match Command::new("cc").args(["-std=c99", "-w", "-o"]).arg(&binary).arg(&c_file).status() {
+            Ok(status) => status,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {
+                eprintln!("skipping, as there is no `cc`");
+                return;
+            }
+            Err(error) => panic!("could not run `cc`: {}", error),
+        };
+        assert!(status.success(), "{} does not compile", source);
+
+        // the messages of the C runtime are its own
+        let (output, error) = run_compiled(&mut Command::new(&binary), input);
+        let (expected_output, expected_error) = interpret(source, input);
+
+        assert_eq!(output, expected_output, "{}", source);
+        assert_eq!(error.is_some(), expected_error.is_some(), "{} failed with {:?}", source, error);
+    }
+}
+
+#[test]
+fn native_state_refuses_instructions_of_the_program() {
+    use Instruction::*;
+
+    for instruction in [StartLoop, EndLoop, ToggleStringMode { kind: StringModeKind::Single }, Evaluate, Kill] {
+        let mut state = NativeState::<HashGrid>::new();
+        let result = state.execute(instruction, &mut io::empty(), &mut io::sink());
+
+        assert!(
+            matches!(result, Err(Error::Unsupported { instruction: i, .. }) if i == instruction),
+            "{:?}",
+            result,
+        );
+    }
+}
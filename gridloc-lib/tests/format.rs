@@ -0,0 +1,41 @@
+//! Formatting a program must settle on one layout, and must never change
+//! what the program does.
+
+use gridloc::*;
+
+const PROGRAMS: &[&str] = &[
+    "",
+    "\n\n\n",
+    "'Hi'(0q)<..s>g;n",
+    "  01 , 03 [ n ( . '[' ) - ] @  ",
+    "# a comment\n\n\n\n01,  # and another\n#\n\nn",
+    "01,03[\n    n  # print\n    02[-]\n\n\n-]\n",
+    "[[[\n]]]",
+    "[ [ ] # inner\n]x",
+    "'a\\'b' \"c\\\"d\" 'e\\\\f\\n\\t\\0\\x7F\\x80\\q'",
+    "'a literal\n  over # several\n\nlines'  ('more')s",
+    "'héllo ☃' wörld ☃ \u{a0} x",
+    "(\"v.01,02[n(.'[')-]@\")e(>'ok'):s",
+    "01, 'never closed\n\n",
+    "\t01,\r\n02,\r\n",
+];
+
+fn fmt(source: &str) -> String {
+    Program::parse(source).unwrap().to_string()
+}
+
+#[test]
+fn formatting_twice_changes_nothing() {
+    for source in PROGRAMS {
+        let formatted = fmt(source);
+
+        assert_eq!(fmt(&formatted), formatted, "{:?}", source);
+    }
+}
+
+#[test]
+fn formatting_keeps_the_significant_code() {
+    for source in PROGRAMS {
+        assert_eq!(significant_code(&fmt(source)), significant_code(source), "{:?}", source);
+    }
+}
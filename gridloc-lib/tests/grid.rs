@@ -0,0 +1,249 @@
+//! Every grid must behave like a map from positions to values, in which
+//! cells which were never written are zero.
+
+use std::collections::HashMap;
+
+use ndarray::Array2;
+use proptest::prelude::*;
+
+use gridloc::*;
+
+#[derive(Clone, Debug)]
+enum Op {
+    Get(Position),
+    Set(Position, u8),
+    SetRegion(Position, Array2<u8>),
+}
+
+/// Coordinates around zero, including the chunk boundaries on either side,
+/// at the extremes, and anywhere at all.
+fn coordinate() -> impl Strategy<Value = isize> + Clone {
+    prop_oneof![
+        -40isize..40,
+        isize::MIN..isize::MIN + 40,
+        isize::MAX - 40..=isize::MAX,
+        any::<isize>(),
+    ]
+}
+
+fn position() -> impl Strategy<Value = Position> + Clone {
+    (coordinate(), coordinate()).prop_map(|(x, y)| Position::new(x, y))
+}
+
+/// Zeros are written often, so that chunks are emptied and removed.
+fn value() -> impl Strategy<Value = u8> {
+    prop_oneof![Just(0), any::<u8>()]
+}
+
+/// Small rectangles of values, which may be empty.
+fn values() -> impl Strategy<Value = Array2<u8>> {
+    (0usize..4, 0usize..4).prop_flat_map(|(height, width)| {
+        prop::collection::vec(value(), width * height)
+            .prop_map(move |values| Array2::from_shape_vec((height, width), values).unwrap())
+    })
+}
+
+fn op(position: impl Strategy<Value = Position> + Clone) -> impl Strategy<Value = Op> {
+    prop_oneof![
+        4 => position.clone().prop_map(Op::Get),
+        4 => (position.clone(), value()).prop_map(|(position, value)| Op::Set(position, value)),
+        1 => (position, values()).prop_map(|(position, values)| Op::SetRegion(position, values)),
+    ]
+}
+
+/// A rectangle to read back with [`Grid::region`].
+fn window() -> impl Strategy<Value = (Position, usize, usize)> {
+    (position(), 0usize..12, 0usize..12)
+}
+
+fn ops() -> impl Strategy<Value = Vec<Op>> {
+    prop::collection::vec(op(position()), 0..200)
+}
+
+/// Operations which write near one corner of the grid, as a dense grid
+/// keeps every cell between the written ones. Reads are anywhere.
+fn local_ops() -> impl Strategy<Value = Vec<Op>> {
+    let corner = prop_oneof![Just(isize::MIN), Just(-8), Just(0), Just(isize::MAX)];
+
+    (corner.clone(), corner).prop_flat_map(|(x, y)| {
+        // regions which are written do not wrap around past the largest
+        // coordinate either
+        let near = (-40isize..40, -40isize..40).prop_map(move |(dx, dy)| Position::new(
+            x.saturating_add(dx).min(isize::MAX - 4),
+            y.saturating_add(dy).min(isize::MAX - 4),
+        ));
+        let read = position().prop_map(Op::Get);
+
+        prop::collection::vec(prop_oneof![op(near), read], 0..200)
+    })
+}
+
+/// Run `ops` on `grid` and on a map, where `wrap` finds the cell which a
+/// position refers to, and check that they agree throughout and on the
+/// cells of `window` afterwards.
+fn check<G: Grid>(
+    mut grid: G,
+    ops: &[Op],
+    window: &(Position, usize, usize),
+    wrap: impl Fn(Position) -> Position,
+) -> Result<(), TestCaseError> {
+    let mut reference: HashMap<Position, u8> = HashMap::new();
+
+    for op in ops {
+        match *op {
+            Op::Get(position) => {
+                let expected = reference.get(&wrap(position)).copied().unwrap_or(0);
+                prop_assert_eq!(grid.get(&position), expected, "get {}", position);
+            }
+            Op::Set(position, value) => {
+                let expected = reference.insert(wrap(position), value).unwrap_or(0);
+                prop_assert_eq!(grid.try_set(&position, value), Some(expected), "set {} to {}", position, value);
+            }
+            Op::SetRegion(min, ref values) => {
+                prop_assert_eq!(grid.set_region(&min, values), Some(()), "set region at {}", min);
+
+                for ((row, column), value) in values.indexed_iter() {
+                    reference.insert(wrap(min + Position::new(column as isize, row as isize)), *value);
+                }
+            }
+        }
+    }
+
+    let (min, width, height) = *window;
+    let region = Array2::from_shape_fn((height, width), |(row, column)| {
+        let position = wrap(min + Position::new(column as isize, row as isize));
+        reference.get(&position).copied().unwrap_or(0)
+    });
+    prop_assert_eq!(grid.region(&min, width, height), region);
+
+    let mut expected: Vec<_> = reference.into_iter()
+        .filter(|(_, value)| *value != 0)
+        .collect();
+    expected.sort_by_key(|(position, _)| (position.x, position.y));
+
+    let mut cells: Vec<_> = grid.cells().collect();
+    cells.sort_by_key(|(position, _)| (position.x, position.y));
+
+    let bounds = expected.iter().fold(None, |bounds: Option<(Position, Position)>, (position, _)| Some(match bounds {
+        Some((min, max)) => (
+            Position::new(position.x.min(min.x), position.y.min(min.y)),
+            Position::new(position.x.max(max.x), position.y.max(max.y)),
+        ),
+        None => (*position, *position),
+    }));
+
+    prop_assert_eq!(grid.bounding_box(), bounds);
+    prop_assert_eq!(grid.cell_count(), expected.len());
+    prop_assert_eq!(&cells, &expected);
+
+    grid.clear();
+    prop_assert_eq!(grid.bounding_box(), None);
+    prop_assert_eq!(grid.cell_count(), 0);
+    prop_assert_eq!(grid.cells().count(), 0);
+
+    for (position, _) in expected {
+        prop_assert_eq!(grid.get(&position), 0, "get {} after clearing", position);
+    }
+
+    Ok(())
+}
+
+proptest! {
+    #[test]
+    fn hash_grid_is_a_map(ops in ops(), window in window()) {
+        check(HashGrid::default(), &ops, &window, |position| position)?;
+    }
+
+    #[test]
+    fn hash_grid_is_a_map_locally(ops in local_ops(), window in window()) {
+        check(HashGrid::default(), &ops, &window, |position| position)?;
+    }
+
+    #[test]
+    fn dense_grid_is_a_map(ops in local_ops(), window in window()) {
+        check(DenseGrid::default(), &ops, &window, |position| position)?;
+    }
+
+    #[test]
+    fn dense_grid_refuses_to_grow_too_large(
+        first in position(),
+        second in position(),
+        value in 1u8..,
+        max_area in 1usize..100,
+    ) {
+        let mut grid = DenseGrid::with_max_area(max_area);
+        prop_assert_eq!(grid.try_set(&first, value), Some(0));
+
+        let width = (second.x as i128 - first.x as i128).unsigned_abs() + 1;
+        let height = (second.y as i128 - first.y as i128).unsigned_abs() + 1;
+
+        match grid.try_set(&second, value) {
+            Some(_) => prop_assert!(width * height <= max_area as u128),
+            None => {
+                // the grid is left as it was
+                prop_assert!(width * height > 1);
+                prop_assert_eq!(grid.get(&second), 0);
+                prop_assert_eq!(grid.cell_count(), 1);
+            }
+        }
+
+        prop_assert_eq!(grid.get(&first), value);
+
+        // writing zero never needs to grow
+        prop_assert_eq!(grid.try_set(&Position::new(first.x.wrapping_add(1000), first.y), 0), Some(0));
+    }
+
+    #[test]
+    fn torus_grid_is_a_wrapped_map(width in 1usize..12, height in 1usize..12, ops in ops(), window in window()) {
+        let wrap = |position: Position| Position::new(
+            position.x.rem_euclid(width as isize),
+            position.y.rem_euclid(height as isize),
+        );
+
+        check(TorusGrid::new(width, height), &ops, &window, wrap)?;
+    }
+}
+
+#[test]
+fn grids_can_be_shared_between_threads() {
+    fn assert_send_sync<G: Send + Sync>() {}
+
+    assert_send_sync::<HashGrid>();
+    assert_send_sync::<DenseGrid>();
+    assert_send_sync::<TorusGrid>();
+}
+
+#[test]
+fn hash_grid_reads_and_writes_regions_across_chunks() {
+    // a region which wraps around on both axes
+    let min = Position::new(isize::MAX - 9, isize::MIN + 3);
+    let values = Array2::from_shape_fn((21, 19), |(row, column)| (row * 19 + column) as u8 % 7);
+    let around = min - Position::new(5, 5);
+    let mut expected = Array2::<u8>::zeros((30, 30));
+    expected.slice_mut(ndarray::s![5..26, 5..24]).assign(&values);
+
+    // with few chunks, which are walked to read the region around it
+    let mut grid = HashGrid::default();
+    assert_eq!(grid.set_region(&min, &values), Some(()));
+    assert_eq!(grid.region(&around, 30, 30), expected);
+    assert_eq!(grid.cell_count(), values.iter().filter(|value| **value != 0).count());
+
+    // and with many chunks elsewhere, so that the chunks under the region
+    // are looked up instead
+    for i in 0..100 {
+        grid.set(&Position::new(i * 8, 0), 1);
+    }
+    assert_eq!(grid.region(&around, 30, 30), expected);
+
+    assert_eq!(grid.set_region(&min, &Array2::<u8>::zeros((21, 19))), Some(()));
+    assert_eq!(grid.region(&around, 30, 30), Array2::<u8>::zeros((30, 30)));
+    assert_eq!(grid.cell_count(), 100);
+}
+
+#[test]
+fn dense_grid_refuses_regions_which_are_too_large() {
+    let mut grid = DenseGrid::with_max_area(16);
+
+    assert_eq!(grid.set_region(&Position::new(0, 0), &Array2::from_elem((5, 5), 1)), None);
+    assert!(grid.cell_count() <= 16);
+}
@@ -0,0 +1,138 @@
+//! Stepping back must leave a program exactly as it was before the step, so
+//! that stepping forwards again does the same thing.
+
+use std::io;
+
+use gridloc::*;
+
+mod common;
+use common::Output;
+
+/// Programs and the input they are given.
+const PROGRAMS: &[(&str, &str)] = &[
+    // `e` pushes a tape and a pointer, which `@` kills and pops again
+    ("(\"01,n@\")e02,n", ""),
+    ("(\"v.01,02[n(.'[')-]@\")e(>'ok'):s", ""),
+    // killing the only pointer ends the program
+    ("01,n@", ""),
+    // input which is read again after stepping back
+    ("(N)n.(X)x.(S)s", "42\nz\nrest\n"),
+    // a step which fails
+    ("05,(00,)%n", ""),
+];
+
+fn build(source: &str, input: &str, output: &Output) -> Interpreter<'static, HashGrid> {
+    InterpreterBuilder::from_source(source)
+        .reader(Box::new(io::Cursor::new(input.to_string())))
+        .writer(Box::new(output.clone()))
+        .record_history(1000)
+        .seed(0)
+        .build()
+        .unwrap()
+}
+
+fn save(interpreter: &Interpreter<HashGrid>) -> String {
+    let mut snapshot = Vec::new();
+    interpreter.snapshot().write_to(&mut snapshot).unwrap();
+
+    String::from_utf8(snapshot).unwrap()
+}
+
+/// Step to the end, returning the snapshot before each counted step and the
+/// one after the last. A step which fails is counted as well.
+fn step_to_end(interpreter: &mut Interpreter<HashGrid>) -> Vec<String> {
+    let mut snapshots = vec![save(interpreter)];
+
+    loop {
+        let steps = interpreter.state().steps();
+        let more = interpreter.step();
+
+        if interpreter.state().steps() != steps {
+            snapshots.push(save(interpreter));
+        }
+
+        if !matches!(more, Ok(true)) {
+            return snapshots;
+        }
+    }
+}
+
+#[test]
+fn stepping_back_undoes_each_step() {
+    for (source, input) in PROGRAMS {
+        let output = Output::default();
+        let mut interpreter = build(source, input, &output);
+        let snapshots = step_to_end(&mut interpreter);
+        let first_output = output.0.take();
+        assert_eq!(interpreter.state().history_len(), snapshots.len() - 1, "{}", source);
+
+        for (steps, snapshot) in snapshots.iter().enumerate().rev().skip(1) {
+            assert!(interpreter.step_back(), "{} back to step {}", source, steps);
+            assert_eq!(&save(&interpreter), snapshot, "{} back to step {}", source, steps);
+        }
+        assert!(!interpreter.step_back(), "{}", source);
+
+        // the input is read again, so everything happens again
+        assert_eq!(step_to_end(&mut interpreter), snapshots, "{}", source);
+        assert_eq!(output.0.take(), first_output, "{}", source);
+    }
+}
+
+#[test]
+fn reversing_to_a_write_counts_like_stepping_back() {
+    for (source, input) in PROGRAMS {
+        let mut reversed = Debugger::new(build(source, input, &Output::default()));
+        let mut stepped = Debugger::new(build(source, input, &Output::default()));
+        while reversed.step().is_ok_and(|reason| reason != StopReason::Finished) {}
+        while stepped.step().is_ok_and(|reason| reason != StopReason::Finished) {}
+
+        let steps = reversed.reverse_to_last_write(&Position::new(0, 0)).unwrap();
+        for _ in 0..steps {
+            stepped.step_back();
+        }
+
+        assert_eq!(reversed.steps(), stepped.steps(), "{}", source);
+        assert_eq!(reversed.is_finished(), stepped.is_finished(), "{}", source);
+        assert_eq!(save(reversed.interpreter()), save(stepped.interpreter()), "{}", source);
+    }
+}
+
+#[test]
+fn stepping_back_after_a_limit_counts_the_steps_undone() {
+    let mut debugger = Debugger::new(
+        InterpreterBuilder::from_source("01,02,03,04,05,")
+            .writer(Box::new(io::sink()))
+            .limits(Limits { max_steps: Some(4), ..Limits::default() })
+            .record_history(1000)
+            .build::<HashGrid>()
+            .unwrap(),
+    );
+
+    let mut snapshots = vec![save(debugger.interpreter())];
+    while let Ok(StopReason::Step) = debugger.step() {
+        snapshots.push(save(debugger.interpreter()));
+    }
+    assert!(debugger.is_finished());
+    assert_eq!(debugger.steps(), 4);
+
+    // the step which hit the limit never ran, so there is nothing of it to undo
+    assert!(debugger.step_back());
+    assert_eq!(debugger.steps(), 3);
+    assert_eq!(save(debugger.interpreter()), snapshots[3]);
+}
+
+#[test]
+fn clearing_the_history_forgets_every_step() {
+    let mut interpreter = build("01,02,03,", "", &Output::default());
+    interpreter.step().unwrap();
+    interpreter.step().unwrap();
+    assert_eq!(interpreter.state().history_len(), 2);
+
+    interpreter.state_mut().clear_history();
+    assert_eq!(interpreter.state().history_len(), 0);
+    assert!(!interpreter.step_back());
+
+    // later steps are recorded again
+    interpreter.step().unwrap();
+    assert!(interpreter.step_back());
+}
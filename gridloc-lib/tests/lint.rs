@@ -0,0 +1,87 @@
+//! Each lint must warn where it should, and only there, unless a directive
+//! allows it.
+
+use gridloc::*;
+
+/// The lints of `source`, with the line and column of each.
+fn lints(source: &str) -> Vec<(LintId, usize, usize)> {
+    lint(source)
+        .into_iter()
+        .map(|lint| (lint.id, lint.position.line, lint.position.column))
+        .collect()
+}
+
+#[test]
+fn unmatched_bracket() {
+    assert_eq!(lints("01[n"), [(LintId::UnmatchedBracket, 1, 3)]);
+    assert_eq!(lints("01[n]"), []);
+    assert_eq!(lints("'['"), []);
+}
+
+#[test]
+fn ignored_character() {
+    assert_eq!(lints("01,hn"), [(LintId::IgnoredCharacter, 1, 4)]);
+    assert_eq!(lints("01, n\t\n"), []);
+    assert_eq!(lints("'hi'"), []);
+}
+
+#[test]
+fn zero_move() {
+    assert_eq!(lints("n:"), [(LintId::ZeroMove, 1, 2)]);
+    assert_eq!(lints("1:"), []);
+    assert_eq!(lints("_:"), []);
+}
+
+#[test]
+fn unreachable_code() {
+    assert_eq!(lints("n@ n"), [(LintId::UnreachableCode, 1, 4)]);
+    assert_eq!(lints("n@  \n"), []);
+    assert_eq!(lints("01[@]n"), []);
+}
+
+#[test]
+fn unsaved_slot() {
+    assert_eq!(lints("05g"), [(LintId::UnsavedSlot, 1, 3)]);
+    assert_eq!(lints("05q05g"), []);
+    // code run with `e` may save to it
+    assert_eq!(lints("05ge"), []);
+}
+
+#[test]
+fn empty_position_stack() {
+    assert_eq!(lints("n)$"), [(LintId::EmptyPositionStack, 1, 2), (LintId::EmptyPositionStack, 1, 3)]);
+    assert_eq!(lints("($)"), []);
+}
+
+#[test]
+fn unterminated_string() {
+    assert_eq!(lints("n'abc"), [(LintId::UnterminatedString, 1, 2)]);
+    assert_eq!(lints("n'abc'"), []);
+}
+
+#[test]
+fn directives_allow_their_own_line_and_the_next() {
+    assert_eq!(lints("n@n  # gridloc: allow(unreachable-code)"), []);
+    assert_eq!(lints("# gridloc: allow(unreachable-code)\nn@n"), []);
+    assert_eq!(lints("# gridloc: allow(unreachable-code)\n\nn@n"), [(LintId::UnreachableCode, 3, 3)]);
+    // only the lints which they name
+    assert_eq!(lints("# gridloc: allow(zero-move)\nn@n"), [(LintId::UnreachableCode, 2, 3)]);
+}
+
+#[test]
+fn file_directives_allow_everywhere() {
+    assert_eq!(lints("h\n\n\nh  # gridloc: allow-file(ignored-character)\n\n\nh"), []);
+    assert_eq!(lints("h # gridloc: allow-file(zero-move)"), [(LintId::IgnoredCharacter, 1, 1)]);
+}
+
+#[test]
+fn directives_of_unknown_lints_are_warned_about() {
+    // the directive does not reach back to the line before it
+    let warnings = lint("n@n\n# gridloc: allow(unreachable-code, no-such-lint)");
+
+    assert_eq!(warnings.len(), 2);
+    assert_eq!(warnings[0].id, LintId::UnreachableCode);
+    assert_eq!(warnings[1].id, LintId::UnknownLint);
+    assert_eq!(warnings[1].position, SourcePosition { line: 2, column: 1 });
+    assert!(warnings[1].message.contains("`no-such-lint`"), "{}", warnings[1].message);
+}
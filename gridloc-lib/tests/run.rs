@@ -0,0 +1,233 @@
+//! Running a program all at once takes shortcuts through its compiled source
+//! code, which must not change what it does compared to running it one step
+//! at a time.
+
+use gridloc::*;
+
+mod common;
+use common::Output;
+
+/// Everything a run leaves behind.
+#[derive(Debug, PartialEq)]
+struct Outcome {
+    output: Vec<u8>,
+    result: Result<(), String>,
+    steps: u64,
+    pointer: Option<Position>,
+    snapshot: String,
+}
+
+fn outcome<G: Grid>(interpreter: &Interpreter<G>, output: Output, result: Result<(), Error>) -> Outcome {
+    let mut snapshot = Vec::new();
+    interpreter.snapshot().write_to(&mut snapshot).unwrap();
+
+    Outcome {
+        output: output.0.take(),
+        result: result.map_err(|error| error.to_string()),
+        steps: interpreter.state().steps(),
+        pointer: interpreter.state().current_pointer().map(|pointer| pointer.borrow().position),
+        snapshot: String::from_utf8(snapshot).unwrap(),
+    }
+}
+
+fn build<G: Grid + 'static>(source: &str, grid: G, limits: Limits, output: &Output) -> Interpreter<'static, G> {
+    InterpreterBuilder::from_source(source)
+        .writer(Box::new(output.clone()))
+        .limits(limits)
+        .seed(0)
+        .build_on(grid)
+        .unwrap()
+}
+
+fn run_at_once<G: Grid + 'static>(source: &str, grid: G, limits: Limits) -> Outcome {
+    let output = Output::default();
+    let mut interpreter = build(source, grid, limits, &output);
+    let result = interpreter.run();
+
+    outcome(&interpreter, output, result)
+}
+
+fn run_step_by_step<G: Grid + 'static>(source: &str, grid: G, limits: Limits) -> Outcome {
+    let output = Output::default();
+    let mut interpreter = build(source, grid, limits, &output);
+
+    let result = loop {
+        match interpreter.step() {
+            Ok(true) => {}
+            Ok(false) => break Ok(()),
+            Err(error) => break Err(error),
+        }
+    };
+
+    outcome(&interpreter, output, result)
+}
+
+#[test]
+fn moves_wrap_around_a_torus() {
+    let source = "..........%";
+    let at_once = run_at_once(source, TorusGrid::new(4, 4), Limits::default());
+
+    assert_eq!(at_once.pointer, Some(Position::new(2, 0)));
+    assert_eq!(at_once, run_step_by_step(source, TorusGrid::new(4, 4), Limits::default()));
+}
+
+#[test]
+fn instructions_wrap_around_a_torus() {
+    let sources = [
+        // writing while moving
+        "41;;;;;;..q",
+        // moving by the pointer value, and reading what was written
+        "07:5,^3:..x",
+        // saving and loading positions past the edge
+        "^......3q0g_n",
+        // a loop which keeps moving
+        "01,(03[)(-....;]q",
+    ];
+
+    for source in sources {
+        assert_eq!(
+            run_at_once(source, TorusGrid::new(3, 5), Limits::default()),
+            run_step_by_step(source, TorusGrid::new(3, 5), Limits::default()),
+            "{}",
+            source,
+        );
+    }
+}
+
+#[test]
+fn strings_without_a_zero_end_after_one_lap_of_a_torus() {
+    for (source, width, height, expected) in [("'abcd's", 4, 1, "abcd"), ("^'abc's", 1, 3, "abc")] {
+        let at_once = run_at_once(source, TorusGrid::new(width, height), Limits::default());
+
+        assert_eq!(at_once.output, expected.as_bytes(), "{}", source);
+        assert_eq!(at_once.result, Ok(()), "{}", source);
+        // back where the string starts
+        assert_eq!(at_once.pointer, Some(Position::new(0, 0)), "{}", source);
+        assert_eq!(at_once, run_step_by_step(source, TorusGrid::new(width, height), Limits::default()), "{}", source);
+    }
+}
+
+#[test]
+fn dividing_by_zero_faults() {
+    for (source, fault) in [("05,(00,)/n", ArithmeticFault::DivideByZero), ("05,(00,)%n", ArithmeticFault::ModuloByZero)] {
+        let mut interpreter = build(source, HashGrid::default(), Limits::default(), &Output::default());
+
+        assert!(
+            matches!(interpreter.run(), Err(Error::Arithmetic { fault: f, .. }) if f == fault),
+            "{}",
+            source,
+        );
+    }
+}
+
+#[test]
+fn distant_writes_to_a_dense_grid_fail() {
+    let source = format!("01,FF{}^{},", ":".repeat(4000), ":".repeat(4000));
+    let at_once = run_at_once(&source, DenseGrid::default(), Limits::default());
+
+    assert_eq!(at_once.result, Err("exceeded the grid size limit at line 1, column 8007 (depth 0) \
+        with the pointer at (1020000, 1020000)".to_string()));
+    assert_eq!(at_once, run_step_by_step(&source, DenseGrid::default(), Limits::default()));
+}
+
+#[test]
+fn brackets_in_literals_are_data() {
+    // jumping forwards past `']'`, in the source code and in code evaluated
+    // from the grid
+    let forwards = "[']']2A,x";
+    // and backwards past `'['`, which the loop writes below itself
+    let backwards = "v.01,02[n(.'[')-]@";
+
+    for (source, output) in [
+        (forwards.to_string(), "*"),
+        (format!("(\"{}@\")e", forwards), "*"),
+        (format!("(\"{}\")e", backwards), "11"),
+    ] {
+        let at_once = run_at_once(&source, HashGrid::default(), Limits::default());
+
+        assert_eq!(at_once.result, Ok(()), "{}", source);
+        assert_eq!(at_once.output, output.as_bytes(), "{}", source);
+        assert_eq!(at_once, run_step_by_step(&source, HashGrid::default(), Limits::default()), "{}", source);
+    }
+}
+
+#[test]
+fn nested_loops_are_jumped_over() {
+    let source = "00[01,[.]n]02,n";
+
+    for source in [source.to_string(), format!("(\"{}@\")e", source)] {
+        let at_once = run_at_once(&source, HashGrid::default(), Limits::default());
+
+        assert_eq!(at_once.output, b"2", "{}", source);
+        assert_eq!(at_once, run_step_by_step(&source, HashGrid::default(), Limits::default()), "{}", source);
+    }
+}
+
+#[test]
+fn running_at_once_matches_stepping_within_limits() {
+    let sources = [
+        // nested loops, and a loop around a string literal
+        "01,.03,[<.>02[n-]._<.>-.,]n",
+        "v.01,03[n(.'[')-]@",
+        // code evaluated from the grid, which kills its pointer
+        "(\"v.01,02[n(.'[')-]@\")e(>'ok'):s",
+        // a literal which never ends
+        "'abc",
+        // a fault in the middle of the compiled code
+        "05,(00,)%n",
+        // killing the only pointer
+        "01n@02n",
+    ];
+    let limits = [
+        Limits::default(),
+        Limits { max_steps: Some(7), ..Limits::default() },
+        Limits { max_steps: Some(20), ..Limits::default() },
+        Limits { max_output: Some(1), ..Limits::default() },
+        Limits { max_cells: Some(2), ..Limits::default() },
+        Limits { max_position_stack: Some(0), ..Limits::default() },
+    ];
+
+    for source in sources {
+        for limits in limits {
+            assert_eq!(
+                run_at_once(source, HashGrid::default(), limits),
+                run_step_by_step(source, HashGrid::default(), limits),
+                "{} within {:?}",
+                source,
+                limits,
+            );
+        }
+    }
+}
+
+/// A grid of any size on which every cell is one, so that no string on it
+/// ever ends.
+#[derive(Default)]
+struct Ones;
+
+impl Grid for Ones {
+    fn get(&self, _pos: &Position) -> u8 {
+        1
+    }
+
+    fn try_set(&mut self, _pos: &Position, _value: u8) -> Option<u8> {
+        Some(1)
+    }
+
+    fn cell_count(&self) -> usize {
+        0
+    }
+
+    fn cells(&self) -> Box<dyn Iterator<Item = (Position, u8)> + '_> {
+        Box::new(std::iter::empty())
+    }
+}
+
+#[test]
+fn strings_stop_once_they_are_too_long() {
+    let limits = Limits { max_output: Some(100), ..Limits::default() };
+    let at_once = run_at_once("s", Ones, limits);
+
+    assert!(at_once.result.as_ref().is_err_and(|error| error.starts_with("exceeded the output")), "{:?}", at_once.result);
+    assert!(at_once.output.is_empty());
+}
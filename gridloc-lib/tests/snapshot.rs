@@ -0,0 +1,150 @@
+//! A program which is stopped, saved and resumed from its snapshot must do
+//! the same as one which ran without stopping.
+
+use std::io;
+use std::io::Write;
+
+use gridloc::*;
+
+mod common;
+use common::Output;
+
+const PROGRAMS: &[&str] = &[
+    "'Hi'(0q)<..s>g;n",
+    "v.01,03[n(.'[')-]@",
+    "(\"[']']2A,x@\")e0A,x",
+    "(\"v.01,02[n(.'[')-]@\")e(>'ok'):s",
+];
+
+/// Run `builder` to the end, returning its output and final snapshot.
+fn finish(builder: InterpreterBuilder<'static>) -> (Vec<u8>, String) {
+    let output = Output::default();
+    let mut interpreter = builder
+        .writer(Box::new(output.clone()))
+        .build::<HashGrid>()
+        .unwrap();
+    interpreter.run().unwrap();
+
+    (output.0.take(), save(&interpreter))
+}
+
+fn save(interpreter: &Interpreter<HashGrid>) -> String {
+    let mut snapshot = Vec::new();
+    interpreter.snapshot().write_to(&mut snapshot).unwrap();
+
+    String::from_utf8(snapshot).unwrap()
+}
+
+#[test]
+fn resuming_does_what_running_does() {
+    for source in PROGRAMS {
+        let (expected_output, expected_snapshot) = finish(InterpreterBuilder::from_source(source));
+        let steps: u64 = serde_json::from_str::<serde_json::Value>(&expected_snapshot).unwrap()["steps"]
+            .as_u64()
+            .unwrap();
+
+        // stop after every number of steps, including none at all
+        for max_steps in 0..=steps {
+            let output = Output::default();
+            let mut interpreter = InterpreterBuilder::from_source(source)
+                .writer(Box::new(output.clone()))
+                .limits(Limits { max_steps: Some(max_steps), ..Limits::default() })
+                .build::<HashGrid>()
+                .unwrap();
+
+            let result = interpreter.run();
+            assert!(
+                matches!(result, Err(Error::ResourceLimit { limit: Limit::Steps, .. })) || max_steps == steps,
+                "{} stopped after {} steps with {:?}",
+                source,
+                max_steps,
+                result,
+            );
+
+            let snapshot = Snapshot::read_from(save(&interpreter).as_bytes()).unwrap();
+            let (rest, resumed_snapshot) = finish(InterpreterBuilder::from_snapshot(snapshot));
+
+            let mut resumed_output = output.0.take();
+            resumed_output.extend(rest);
+
+            assert_eq!(resumed_output, expected_output, "{} resumed after {} steps", source, max_steps);
+            assert_eq!(resumed_snapshot, expected_snapshot, "{} resumed after {} steps", source, max_steps);
+        }
+    }
+}
+
+/// A snapshot of `source` once it has finished, edited by `edit`.
+fn edited_snapshot(source: &str, edit: impl FnOnce(&mut serde_json::Value)) -> Snapshot {
+    let (_, snapshot) = finish(InterpreterBuilder::from_source(source));
+    let mut snapshot = serde_json::from_str(&snapshot).unwrap();
+    edit(&mut snapshot);
+
+    serde_json::from_value(snapshot).unwrap()
+}
+
+#[test]
+fn snapshots_which_can_not_be_resumed_fail_to_build() {
+    let unbound = edited_snapshot("", |snapshot| {
+        snapshot["tapes"] = serde_json::json!([{ "Grid": { "pointer": 5 } }]);
+    });
+    assert!(matches!(
+        InterpreterBuilder::from_snapshot(unbound).build::<HashGrid>(),
+        Err(BuildError::Snapshot(SnapshotError::UnboundTape(5))),
+    ));
+
+    // a grid tape which reads with the pointer pushed along with it
+    let above = edited_snapshot("", |snapshot| {
+        let pointer = snapshot["pointers"][0].clone();
+        snapshot["pointers"] = serde_json::json!([pointer, pointer]);
+        snapshot["tapes"].as_array_mut().unwrap().push(serde_json::json!({ "Grid": { "pointer": 1 } }));
+    });
+    assert!(matches!(
+        InterpreterBuilder::from_snapshot(above).build::<HashGrid>(),
+        Err(BuildError::Snapshot(SnapshotError::UnboundTape(1))),
+    ));
+
+    let uneven = edited_snapshot("", |snapshot| {
+        let pointer = snapshot["pointers"][0].clone();
+        snapshot["pointers"] = serde_json::json!([pointer, pointer]);
+    });
+    assert!(matches!(
+        InterpreterBuilder::from_snapshot(uneven).build::<HashGrid>(),
+        Err(BuildError::Snapshot(SnapshotError::UnevenStacks { pointers: 2, tapes: 1 })),
+    ));
+
+    let version = edited_snapshot("", |snapshot| snapshot["version"] = 2.into());
+    assert!(matches!(
+        InterpreterBuilder::from_snapshot(version).build::<HashGrid>(),
+        Err(BuildError::Snapshot(SnapshotError::Version(2))),
+    ));
+
+    // the cells are further apart than the grid may grow
+    let far_apart = edited_snapshot("01,FF:::,", |_| {});
+    assert!(matches!(
+        InterpreterBuilder::from_snapshot(far_apart).build_on(DenseGrid::with_max_area(16)),
+        Err(BuildError::Snapshot(SnapshotError::GridSize)),
+    ));
+}
+
+/// A writer which takes everything, but fails to flush it.
+struct Unflushable;
+
+impl Write for Unflushable {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Err(io::Error::other("disk full"))
+    }
+}
+
+#[test]
+fn writing_fails_if_flushing_fails() {
+    let interpreter = InterpreterBuilder::from_source("1,").build::<HashGrid>().unwrap();
+
+    assert!(matches!(
+        interpreter.snapshot().write_to(Unflushable),
+        Err(SnapshotError::Io(_)),
+    ));
+}
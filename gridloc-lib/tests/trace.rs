@@ -0,0 +1,55 @@
+//! Traces which are buffered are written out once the program has run.
+
+use std::io;
+use std::io::BufWriter;
+
+use gridloc::*;
+
+mod common;
+use common::Output;
+
+#[test]
+fn buffered_traces_are_flushed_when_the_run_ends() {
+    let trace = Output::default();
+    let mut interpreter = InterpreterBuilder::from_source("'ab'1,x")
+        .tracer(Box::new(JsonLinesTracer::new(BufWriter::with_capacity(1 << 16, trace.clone()))))
+        .build::<HashGrid>()
+        .unwrap();
+    interpreter.run().unwrap();
+
+    // one record for every step, while the interpreter is still around
+    let trace = trace.0.take();
+    assert_eq!(trace.iter().filter(|byte| **byte == b'\n').count(), 7);
+}
+
+/// A tracer which fails to write out its records.
+struct Unflushable;
+
+impl Tracer for Unflushable {
+    fn trace(&mut self, _record: &TraceRecord) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Err(io::Error::other("disk full"))
+    }
+}
+
+#[test]
+fn failing_to_flush_is_an_error() {
+    let mut interpreter = InterpreterBuilder::from_source("1,x")
+        .tracer(Box::new(Unflushable))
+        .build::<HashGrid>()
+        .unwrap();
+
+    assert!(matches!(interpreter.run(), Err(Error::Io(_))));
+
+    // stepping leaves flushing to the caller
+    let mut interpreter = InterpreterBuilder::from_source("1,x")
+        .tracer(Box::new(Unflushable))
+        .build::<HashGrid>()
+        .unwrap();
+
+    while interpreter.step().unwrap() {}
+    assert!(matches!(interpreter.state_mut().flush_tracer(), Err(Error::Io(_))));
+}
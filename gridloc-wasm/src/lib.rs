@@ -0,0 +1,221 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::io;
+use std::io::{BufRead, Read, Write};
+use std::rc::Rc;
+
+use wasm_bindgen::prelude::*;
+
+use gridloc::*;
+
+/// What the program is doing after a call to [`Playground::step`].
+#[wasm_bindgen]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Status {
+    /// The program can take more steps.
+    Running,
+    /// The next step reads a line of input, but no full line has been fed.
+    WaitingForInput,
+    /// The program has finished, or faulted.
+    Finished,
+}
+
+/// Input which is fed from JavaScript while the program runs.
+#[derive(Default)]
+struct Input {
+    pending: VecDeque<u8>,
+    /// Whether no more input will be fed, so that the program reads the rest
+    /// and then the end of the input.
+    closed: bool,
+}
+
+impl Input {
+    /// Whether a read of the next line would not have to wait for more input.
+    fn has_line(&self) -> bool {
+        self.closed || self.pending.contains(&b'\n')
+    }
+}
+
+/// Reads from the shared [`Input`], a line at a time.
+struct InputReader {
+    input: Rc<RefCell<Input>>,
+    buffer: Vec<u8>,
+    position: usize,
+}
+
+impl Read for InputReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let count = available.len().min(buf.len());
+        buf[..count].copy_from_slice(&available[..count]);
+
+        self.consume(count);
+        Ok(count)
+    }
+}
+
+impl BufRead for InputReader {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.position >= self.buffer.len() {
+            // only take up to the end of the line, so that what is left is
+            // still pending
+            let mut input = self.input.borrow_mut();
+            let length = input.pending.iter()
+                .position(|&byte| byte == b'\n')
+                .map_or(input.pending.len(), |index| index + 1);
+
+            self.buffer = input.pending.drain(..length).collect();
+            self.position = 0;
+        }
+
+        Ok(&self.buffer[self.position..])
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.position += amount;
+    }
+}
+
+/// Output which is drained from JavaScript.
+#[derive(Clone, Default)]
+struct Output(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A program running in the browser, which is stepped from JavaScript.
+#[wasm_bindgen]
+#[derive(Default)]
+pub struct Playground {
+    interpreter: Option<Interpreter<'static, HashGrid>>,
+    input: Rc<RefCell<Input>>,
+    output: Output,
+    finished: bool,
+}
+
+#[wasm_bindgen]
+impl Playground {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Playground {
+        Playground::default()
+    }
+
+    /// Load `source` in place of the current program, clearing the input and
+    /// output.
+    pub fn load(&mut self, source: &str) -> Result<(), JsError> {
+        self.input = Rc::default();
+        self.output = Output::default();
+        self.finished = false;
+
+        let reader = InputReader {
+            input: self.input.clone(),
+            buffer: Vec::new(),
+            position: 0,
+        };
+
+        let interpreter = InterpreterBuilder::from_source(source)
+            .reader(Box::new(reader))
+            .writer(Box::new(self.output.clone()))
+            .build();
+
+        match interpreter {
+            Ok(interpreter) => {
+                self.interpreter = Some(interpreter);
+                Ok(())
+            }
+            Err(error) => {
+                self.interpreter = None;
+                Err(JsError::new(&error.to_string()))
+            }
+        }
+    }
+
+    /// Take up to `count` steps, stopping early if the program finishes or
+    /// waits for input.
+    pub fn step(&mut self, count: u32) -> Result<Status, JsError> {
+        let interpreter = match &mut self.interpreter {
+            Some(interpreter) if !self.finished => interpreter,
+            _ => return Ok(Status::Finished),
+        };
+
+        for _ in 0..count {
+            if let Some(Instruction::Read { .. }) = interpreter.state().peek_instruction() {
+                if !self.input.borrow().has_line() {
+                    return Ok(Status::WaitingForInput);
+                }
+            }
+
+            match interpreter.step() {
+                Ok(true) => {}
+                Ok(false) => {
+                    self.finished = true;
+                    return Ok(Status::Finished);
+                }
+                Err(error) => {
+                    self.finished = true;
+                    return Err(JsError::new(&error.to_string()));
+                }
+            }
+        }
+
+        Ok(Status::Running)
+    }
+
+    /// The values of the cells from `(x0, y0)` to `(x1, y1)`, inclusive, row
+    /// by row from `y0` and column by column from `x0`.
+    #[wasm_bindgen(js_name = getGridRegion)]
+    pub fn grid_region(&self, x0: i32, y0: i32, x1: i32, y1: i32) -> Vec<u8> {
+        let interpreter = match &self.interpreter {
+            Some(interpreter) => interpreter,
+            None => return Vec::new(),
+        };
+        let width = (x1 as i64 - x0 as i64 + 1).max(0) as usize;
+        let height = (y1 as i64 - y0 as i64 + 1).max(0) as usize;
+
+        interpreter.state().grid.borrow()
+            .region(&Position::new(x0 as isize, y0 as isize), width, height)
+            .into_raw_vec()
+    }
+
+    /// The pointers, from the one of the source code to the current one,
+    /// as objects with a `position`, `direction`, `position_stack` and
+    /// `value`.
+    pub fn pointers(&self) -> Result<JsValue, JsError> {
+        let pointers: Vec<Pointer> = match &self.interpreter {
+            Some(interpreter) => interpreter.state().pointers.iter()
+                .map(|pointer| pointer.borrow().clone())
+                .collect(),
+            None => Vec::new(),
+        };
+
+        serde_wasm_bindgen::to_value(&pointers).map_err(|error| JsError::new(&error.to_string()))
+    }
+
+    /// Feed `input` to the program, which reads it a line at a time.
+    #[wasm_bindgen(js_name = feedInput)]
+    pub fn feed_input(&mut self, input: &str) {
+        self.input.borrow_mut().pending.extend(input.bytes());
+    }
+
+    /// Let the program read the rest of the input, even without a line
+    /// break, and then the end of it.
+    #[wasm_bindgen(js_name = closeInput)]
+    pub fn close_input(&mut self) {
+        self.input.borrow_mut().closed = true;
+    }
+
+    /// Take the output which the program wrote since the last call.
+    #[wasm_bindgen(js_name = drainOutput)]
+    pub fn drain_output(&mut self) -> String {
+        let output = std::mem::take(&mut *self.output.0.borrow_mut());
+        String::from_utf8_lossy(&output).into_owned()
+    }
+}
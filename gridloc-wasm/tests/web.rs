@@ -0,0 +1,78 @@
+//! Run with `cargo test -p gridloc-wasm --target wasm32-unknown-unknown`,
+//! which needs `wasm-bindgen-test-runner` from `wasm-bindgen-cli` and Node.js.
+
+#![cfg(target_arch = "wasm32")]
+
+use gridloc_wasm::*;
+
+use wasm_bindgen_test::*;
+
+fn load(source: &str) -> Playground {
+    let mut playground = Playground::new();
+    playground.load(source).unwrap();
+    playground
+}
+
+#[wasm_bindgen_test]
+fn runs_until_finished() {
+    let mut playground = load("'hi' <..>s");
+
+    assert_eq!(playground.step(1000).unwrap(), Status::Finished);
+    assert_eq!(playground.drain_output(), "hi");
+    assert_eq!(playground.drain_output(), "");
+}
+
+#[wasm_bindgen_test]
+fn stops_after_count_steps() {
+    let mut playground = load("'hi'");
+
+    assert_eq!(playground.step(2).unwrap(), Status::Running);
+    assert_eq!(playground.grid_region(0, 0, 2, 0), vec![b'h', 0, 0]);
+}
+
+#[wasm_bindgen_test]
+fn waits_for_input() {
+    let mut playground = load("N<.n");
+
+    assert_eq!(playground.step(10).unwrap(), Status::WaitingForInput);
+    playground.feed_input("4");
+    assert_eq!(playground.step(10).unwrap(), Status::WaitingForInput);
+    playground.feed_input("2\n");
+    assert_eq!(playground.step(10).unwrap(), Status::Finished);
+    assert_eq!(playground.drain_output(), "42");
+}
+
+#[wasm_bindgen_test]
+fn reads_the_end_of_closed_input() {
+    let mut playground = load("S<...>s");
+
+    playground.feed_input("abc");
+    playground.close_input();
+    assert_eq!(playground.step(100).unwrap(), Status::Finished);
+    assert_eq!(playground.drain_output(), "abc");
+}
+
+#[wasm_bindgen_test]
+fn reads_grid_regions_row_by_row() {
+    let mut playground = load("'ab' ^. < 'c'");
+
+    playground.step(100).unwrap();
+    assert_eq!(playground.grid_region(0, 0, 2, 1), vec![b'a', b'b', 0, 0, 0, b'c']);
+}
+
+#[wasm_bindgen_test]
+fn lists_pointers() {
+    let mut playground = load("v 5");
+
+    playground.step(100).unwrap();
+    let pointers = js_sys::JSON::stringify(&playground.pointers().unwrap()).unwrap();
+    assert_eq!(
+        String::from(pointers),
+        r#"[{"position":{"x":0,"y":0},"direction":"Down","position_stack":[],"value":5}]"#,
+    );
+}
+
+#[wasm_bindgen_test]
+fn rejects_invalid_programs() {
+    assert!(Playground::new().load("[").is_err());
+}